@@ -1,38 +1,2363 @@
 #![no_std]
-use reflector::{Asset as ReflectorAsset, ReflectorClient};
-use soroban_sdk::{contract, contractimpl, Address, Env, Symbol};
+use math::{
+    apply_bps, change_bps, checked_mul_div, cross_rate, deviation_bps, geometric_mean, invert,
+    mul_div_round, rescale,
+};
+use reflector::{Asset as ReflectorAsset, Error, PriceData, ReflectorClient};
+use soroban_sdk::{
+    contract, contractimpl, contracttype, panic_with_error, Address, Env, Symbol, SymbolStr,
+    TryFromVal, Vec,
+};
 
+mod math;
 mod reflector;
+#[cfg(test)]
+mod test;
+
+const MAX_BATCH_SIZE: u32 = 20;
+const HISTORY_SIZE: u32 = 24;
+// 24 hours of 5-minute records, the retention Reflector guarantees.
+const MAX_LOOKBACK_RECORDS: u32 = 288;
+
+const DAY_IN_LEDGERS: u32 = 17280;
+const INSTANCE_BUMP_AMOUNT: u32 = 7 * DAY_IN_LEDGERS;
+const INSTANCE_LIFETIME_THRESHOLD: u32 = INSTANCE_BUMP_AMOUNT - DAY_IN_LEDGERS;
+const PERSISTENT_BUMP_AMOUNT: u32 = 30 * DAY_IN_LEDGERS;
+const PERSISTENT_LIFETIME_THRESHOLD: u32 = PERSISTENT_BUMP_AMOUNT - DAY_IN_LEDGERS;
+
+#[contracttype]
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum DataKey {
+    Admin,
+    PendingAdmin,
+    Oracle,
+    OracleVersion,
+    FallbackOracle,
+    TertiaryOracle,
+    Oracles,
+    Strategy,
+    Decimals(Address),
+    Base(Address),
+    MaxAge,
+    Denomination,
+    Paused,
+    OutputDecimals,
+    Failures,
+    BreakerThreshold,
+    BreakerCountsMissing,
+    DefaultCacheTtl,
+    Allowed(Symbol),
+    Alias(Symbol),
+    Bounds(Symbol),
+    AssetMaxAge(Symbol),
+    High(Symbol),
+    Low(Symbol),
+    History(Symbol),
+    LastEmitted(Symbol),
+    Locked(Symbol, u64),
+    LastGood(Symbol),
+    Manual(Symbol),
+    Cache(Symbol),
+    CacheTtl(Symbol),
+    LedgerCache(Address, ReflectorAsset),
+}
+
+#[contracttype]
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ManualPrice {
+    pub price: i128,
+    pub expires_at: u64,
+}
+
+#[contracttype]
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct CachedPrice {
+    pub price_data: PriceData,
+    pub fetched_at: u64,
+}
+
+#[contracttype]
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct PriceHistory {
+    pub entries: Vec<PriceData>,
+    pub next: u32,
+}
+
+#[contracttype]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Rounding {
+    Floor,
+    Ceil,
+    HalfUp,
+    Trunc,
+}
+
+/// Where `last_good_price` took its answer from.
+#[contracttype]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum PriceSource {
+    Live,
+    LastGood,
+    Manual,
+}
+
+/// How `agg_price` combines the prices of the configured oracles.
+#[contracttype]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum AggStrategy {
+    First,
+    Median,
+    Mean,
+    Max,
+    Min,
+}
 
 #[contract]
 pub struct Contract;
 
 #[contractimpl]
 impl Contract {
-    pub fn __constructor(env: Env) {
-        let oracle_address = Address::from_str(
-            &env,
-            "CCYOZJCOPG34LLQQ7N24YXBM7LL62R7ONMZ3G6WZAAYPB5OYKOMJRN63",
-        );
+    #[allow(clippy::too_many_arguments)]
+    pub fn __constructor(
+        env: Env,
+        admin: Address,
+        oracle: Address,
+        fallback_oracle: Option<Address>,
+        tertiary_oracle: Option<Address>,
+        default_max_age_secs: u64,
+        denomination: Symbol,
+        strategy: AggStrategy,
+    ) {
+        // Catch a wrong address at deployment instead of at the first read.
+        let oracle_version =
+            probe_oracle(&env, &oracle).unwrap_or_else(|error| panic_with_error!(&env, error));
+        for extra in [&fallback_oracle, &tertiary_oracle].into_iter().flatten() {
+            // A second copy of the primary would only echo it.
+            if *extra == oracle {
+                panic_with_error!(&env, Error::AssetAlreadyExists);
+            }
+            probe_oracle(&env, extra).unwrap_or_else(|error| panic_with_error!(&env, error));
+        }
+        env.storage().instance().set(&DataKey::Oracle, &oracle);
+        env.storage()
+            .instance()
+            .set(&DataKey::OracleVersion, &oracle_version);
+        env.storage().instance().set(&DataKey::Admin, &admin);
+        env.storage()
+            .instance()
+            .set(&DataKey::MaxAge, &default_max_age_secs);
         env.storage()
             .instance()
-            .set(&Symbol::new(&env, "oracle"), &oracle_address);
+            .set(&DataKey::Denomination, &denomination);
+        env.storage().instance().set(&DataKey::Strategy, &strategy);
+
+        if let Some(fallback_oracle) = &fallback_oracle {
+            env.storage()
+                .instance()
+                .set(&DataKey::FallbackOracle, fallback_oracle);
+        }
+        if let Some(tertiary_oracle) = &tertiary_oracle {
+            env.storage()
+                .instance()
+                .set(&DataKey::TertiaryOracle, tertiary_oracle);
+        }
+
+        // The aggregation pool starts out as the distinct oracles given here
+        // and is managed independently afterwards.
+        let mut oracles = Vec::new(&env);
+        for candidate in [Some(oracle), fallback_oracle, tertiary_oracle]
+            .into_iter()
+            .flatten()
+        {
+            if !oracles.contains(&candidate) {
+                oracles.push_back(candidate);
+            }
+        }
+        env.storage().instance().set(&DataKey::Oracles, &oracles);
     }
 
-    pub fn hello_xlm(env: Env) -> i128 {
-        let oracle_address: Address = env
-            .storage()
+    pub fn propose_admin(
+        env: Env,
+        current_admin: Address,
+        new_admin: Address,
+    ) -> Result<(), Error> {
+        require_admin(&env, &current_admin)?;
+
+        env.storage()
             .instance()
-            .get(&Symbol::new(&env, "oracle"))
-            .unwrap();
+            .set(&DataKey::PendingAdmin, &new_admin);
+        Ok(())
+    }
+
+    pub fn accept_admin(env: Env, new_admin: Address) -> Result<(), Error> {
+        new_admin.require_auth();
+
+        let key = DataKey::PendingAdmin;
+        let pending_admin: Option<Address> = env.storage().instance().get(&key);
+        if pending_admin != Some(new_admin.clone()) {
+            return Err(Error::Unauthorized);
+        }
+
+        env.storage().instance().set(&DataKey::Admin, &new_admin);
+        env.storage().instance().remove(&key);
+        Ok(())
+    }
+
+    pub fn get_admin(env: Env) -> Address {
+        env.storage().instance().get(&DataKey::Admin).unwrap()
+    }
+
+    pub fn set_oracle(env: Env, admin: Address, new_oracle: Address) -> Result<(), Error> {
+        require_admin(&env, &admin)?;
+        let oracle_version = probe_oracle(&env, &new_oracle)?;
+
+        env.storage().instance().set(&DataKey::Oracle, &new_oracle);
+        env.storage()
+            .instance()
+            .set(&DataKey::OracleVersion, &oracle_version);
+        env.storage()
+            .instance()
+            .remove(&DataKey::Decimals(new_oracle.clone()));
+        env.storage().instance().remove(&DataKey::Base(new_oracle));
+        // Failures counted against the previous oracle do not carry over.
+        env.storage().instance().set(&DataKey::Failures, &0u32);
+        Ok(())
+    }
+
+    /// Adds `oracle` to the pool `agg_price` reads from.
+    pub fn add_oracle(env: Env, admin: Address, oracle: Address) -> Result<(), Error> {
+        require_admin(&env, &admin)?;
+
+        let mut oracles = aggregation_oracles(&env);
+        if oracles.contains(&oracle) {
+            return Err(Error::AssetAlreadyExists);
+        }
+        if oracles.len() >= MAX_BATCH_SIZE {
+            return Err(Error::AssetLimitExceeded);
+        }
+        probe_oracle(&env, &oracle)?;
+
+        oracles.push_back(oracle);
+        env.storage().instance().set(&DataKey::Oracles, &oracles);
+        Ok(())
+    }
+
+    pub fn remove_oracle(env: Env, admin: Address, oracle: Address) -> Result<(), Error> {
+        require_admin(&env, &admin)?;
+
+        let mut oracles = aggregation_oracles(&env);
+        let index = oracles.first_index_of(&oracle).ok_or(Error::AssetMissing)?;
+        oracles.remove(index);
+        env.storage().instance().set(&DataKey::Oracles, &oracles);
+        Ok(())
+    }
+
+    pub fn set_strategy(env: Env, admin: Address, strategy: AggStrategy) -> Result<(), Error> {
+        require_admin(&env, &admin)?;
+
+        env.storage().instance().set(&DataKey::Strategy, &strategy);
+        Ok(())
+    }
+
+    pub fn refresh_decimals(env: Env, admin: Address) -> Result<u32, Error> {
+        require_admin(&env, &admin)?;
+
+        let reflector_client = oracle_client(&env);
+        let decimals = reflector_client.decimals();
+        env.storage()
+            .instance()
+            .set(&DataKey::Decimals(reflector_client.address), &decimals);
+        Ok(decimals)
+    }
 
-        let reflector_client = ReflectorClient::new(&env, &oracle_address);
+    pub fn set_max_age(env: Env, admin: Address, secs: u64) -> Result<(), Error> {
+        require_admin(&env, &admin)?;
+
+        env.storage().instance().set(&DataKey::MaxAge, &secs);
+        Ok(())
+    }
+
+    /// Sets the fixed-point scale every getter that returns a price answers
+    /// in: single-asset prices live or historical, TWAPs, cross rates, the
+    /// indexes built from them and the thresholds they are compared with
+    /// (`set_bounds`, `is_above`, `is_below`). Until this is called they
+    /// return the oracle's own decimals. `PriceData` records, prices stored
+    /// by the contract (`history`, watermarks, `locked_price`) and amounts
+    /// such as `value_of` stay in oracle units.
+    pub fn set_output_decimals(env: Env, admin: Address, decimals: u32) -> Result<(), Error> {
+        require_admin(&env, &admin)?;
+
+        env.storage()
+            .instance()
+            .set(&DataKey::OutputDecimals, &decimals);
+        Ok(())
+    }
+
+    pub fn set_denomination(env: Env, admin: Address, symbol: Symbol) -> Result<(), Error> {
+        require_admin(&env, &admin)?;
+
+        env.storage()
+            .instance()
+            .set(&DataKey::Denomination, &symbol);
+        Ok(())
+    }
+
+    pub fn pause(env: Env, admin: Address) -> Result<(), Error> {
+        require_admin(&env, &admin)?;
+
+        env.storage().instance().set(&DataKey::Paused, &true);
+        Ok(())
+    }
+
+    /// Lifts the pause and clears the breaker's failure count, so the
+    /// breaker needs a full run of failures to trip again.
+    pub fn unpause(env: Env, admin: Address) -> Result<(), Error> {
+        require_admin(&env, &admin)?;
+
+        env.storage().instance().set(&DataKey::Failures, &0u32);
+        env.storage().instance().set(&DataKey::Paused, &false);
+        Ok(())
+    }
+
+    /// Arms the circuit breaker: once `threshold` consecutive reads from the
+    /// configured oracle have failed, the contract pauses itself. Reverts
+    /// always count; with `count_missing` a `None` counts too, which also
+    /// lets any caller add failures by asking for an unlisted symbol. A
+    /// `threshold` of 0 disarms the breaker.
+    pub fn set_breaker_threshold(
+        env: Env,
+        admin: Address,
+        threshold: u32,
+        count_missing: bool,
+    ) -> Result<(), Error> {
+        require_admin(&env, &admin)?;
+
+        env.storage()
+            .instance()
+            .set(&DataKey::BreakerThreshold, &threshold);
+        env.storage()
+            .instance()
+            .set(&DataKey::BreakerCountsMissing, &count_missing);
+        Ok(())
+    }
+
+    /// Keeper entry point for the circuit breaker. Reads XLM from the
+    /// configured oracle and reports whether a price came back. It never
+    /// fails, so the failures it counts, and a trip, are kept; getters that
+    /// fail roll theirs back. Returns `false` once paused.
+    pub fn heartbeat(env: Env) -> bool {
+        if is_paused(&env) {
+            return false;
+        }
+        let reflector_client = ReflectorClient::new(&env, &oracle_address(&env));
         let xlm_asset = ReflectorAsset::Other(Symbol::new(&env, "XLM"));
-        let price_data = reflector_client.lastprice(&xlm_asset);
+        read_price(&env, &reflector_client, &xlm_asset).is_some()
+    }
 
-        match price_data {
-            Some(data) => data.price,
-            None => -1,
+    /// Clears the failure count and lifts the pause, whether the breaker or
+    /// the admin set it.
+    pub fn reset_breaker(env: Env, admin: Address) -> Result<(), Error> {
+        require_admin(&env, &admin)?;
+
+        env.storage().instance().set(&DataKey::Failures, &0u32);
+        env.storage().instance().set(&DataKey::Paused, &false);
+        Ok(())
+    }
+
+    /// Emergency override: until `expires_at` the price getters report
+    /// `price`, in the oracle's decimals, for `symbol` instead of asking the
+    /// oracle. Every read of the override publishes a `manual_price` event.
+    /// It is never stored: `record_price`, `observe`, `poll_and_emit`,
+    /// `lock_price` and the `last_good_price` fallback keep using the oracle,
+    /// as do `self_test`, `heartbeat` and the multi-oracle `checked_price`,
+    /// `median_price` and `agg_price`. `price` must be positive.
+    pub fn set_manual_price(
+        env: Env,
+        admin: Address,
+        symbol: Symbol,
+        price: i128,
+        expires_at: u64,
+    ) -> Result<(), Error> {
+        require_admin(&env, &admin)?;
+        if price <= 0 {
+            return Err(Error::OutOfBounds);
+        }
+        if expires_at < env.ledger().timestamp() {
+            return Err(Error::InvalidTimestamp);
+        }
+
+        let key = DataKey::Manual(symbol);
+        env.storage()
+            .persistent()
+            .set(&key, &ManualPrice { price, expires_at });
+        env.storage().persistent().extend_ttl(
+            &key,
+            PERSISTENT_LIFETIME_THRESHOLD,
+            PERSISTENT_BUMP_AMOUNT,
+        );
+        Ok(())
+    }
+
+    pub fn clear_manual_price(env: Env, admin: Address, symbol: Symbol) -> Result<(), Error> {
+        require_admin(&env, &admin)?;
+
+        env.storage().persistent().remove(&DataKey::Manual(symbol));
+        Ok(())
+    }
+
+    pub fn add_asset(env: Env, admin: Address, symbol: Symbol) -> Result<(), Error> {
+        require_admin(&env, &admin)?;
+
+        let key = DataKey::Allowed(symbol);
+        if env.storage().persistent().has(&key) {
+            return Err(Error::AssetAlreadyExists);
+        }
+        env.storage().persistent().set(&key, &true);
+        env.storage().persistent().extend_ttl(
+            &key,
+            PERSISTENT_LIFETIME_THRESHOLD,
+            PERSISTENT_BUMP_AMOUNT,
+        );
+        Ok(())
+    }
+
+    pub fn remove_asset(env: Env, admin: Address, symbol: Symbol) -> Result<(), Error> {
+        require_admin(&env, &admin)?;
+
+        let key = DataKey::Allowed(symbol);
+        if !env.storage().persistent().has(&key) {
+            return Err(Error::AssetMissing);
+        }
+        env.storage().persistent().remove(&key);
+        Ok(())
+    }
+
+    pub fn register_alias(
+        env: Env,
+        admin: Address,
+        symbol: Symbol,
+        address: Address,
+    ) -> Result<(), Error> {
+        require_admin(&env, &admin)?;
+
+        let key = DataKey::Alias(symbol);
+        env.storage().persistent().set(&key, &address);
+        env.storage().persistent().extend_ttl(
+            &key,
+            PERSISTENT_LIFETIME_THRESHOLD,
+            PERSISTENT_BUMP_AMOUNT,
+        );
+        Ok(())
+    }
+
+    pub fn set_asset_max_age(
+        env: Env,
+        admin: Address,
+        symbol: Symbol,
+        secs: u64,
+    ) -> Result<(), Error> {
+        require_admin(&env, &admin)?;
+
+        let key = DataKey::AssetMaxAge(symbol);
+        env.storage().persistent().set(&key, &secs);
+        env.storage().persistent().extend_ttl(
+            &key,
+            PERSISTENT_LIFETIME_THRESHOLD,
+            PERSISTENT_BUMP_AMOUNT,
+        );
+        Ok(())
+    }
+
+    /// Sets how long `cached_price` may serve a cached `symbol` before it
+    /// asks the oracle again, in place of the `set_default_cache_ttl` value.
+    /// A caller's shorter `max_age_secs` still wins.
+    pub fn set_cache_ttl(env: Env, admin: Address, symbol: Symbol, secs: u64) -> Result<(), Error> {
+        require_admin(&env, &admin)?;
+
+        let key = DataKey::CacheTtl(symbol);
+        env.storage().persistent().set(&key, &secs);
+        env.storage().persistent().extend_ttl(
+            &key,
+            PERSISTENT_LIFETIME_THRESHOLD,
+            PERSISTENT_BUMP_AMOUNT,
+        );
+        Ok(())
+    }
+
+    /// Sets the cache TTL `cached_price` uses for symbols without their own
+    /// `set_cache_ttl`.
+    pub fn set_default_cache_ttl(env: Env, admin: Address, secs: u64) -> Result<(), Error> {
+        require_admin(&env, &admin)?;
+
+        env.storage()
+            .instance()
+            .set(&DataKey::DefaultCacheTtl, &secs);
+        Ok(())
+    }
+
+    /// Bounds for `bounded_price`, in the output decimals.
+    pub fn set_bounds(
+        env: Env,
+        admin: Address,
+        symbol: Symbol,
+        min: i128,
+        max: i128,
+    ) -> Result<(), Error> {
+        require_admin(&env, &admin)?;
+        if min > max {
+            return Err(Error::OutOfBounds);
+        }
+
+        let key = DataKey::Bounds(symbol);
+        env.storage().persistent().set(&key, &(min, max));
+        env.storage().persistent().extend_ttl(
+            &key,
+            PERSISTENT_LIFETIME_THRESHOLD,
+            PERSISTENT_BUMP_AMOUNT,
+        );
+        Ok(())
+    }
+
+    pub fn hello_xlm(env: Env) -> Result<i128, Error> {
+        let xlm = Symbol::new(&env, "XLM");
+        ensure_not_paused(&env);
+        let price_data = match manual_override(&env, &ReflectorAsset::Other(xlm.clone())) {
+            Some(price_data) => price_data,
+            None => {
+                let price_data = fresh_xlm_price(&env)?;
+                remember_good_price(&env, &xlm, &price_data);
+                price_data
+            }
+        };
+        to_output_decimals(&env, &oracle_client(&env), price_data.price)
+            .ok_or(Error::PriceUnavailable)
+    }
+
+    /// Post-deploy smoke test: reads XLM from the configured oracle the way
+    /// `hello_xlm` does and reports `(true, price)`, or `(false, -1)` if the
+    /// contract is paused or no fresh price came back.
+    pub fn self_test(env: Env) -> (bool, i128) {
+        if is_paused(&env) {
+            return (false, -1);
+        }
+        let price = fresh_xlm_price(&env).and_then(|price_data| {
+            to_output_decimals(&env, &oracle_client(&env), price_data.price)
+                .ok_or(Error::PriceUnavailable)
+        });
+        match price {
+            Ok(price) => (true, price),
+            Err(_) => (false, -1),
+        }
+    }
+
+    /// Latest price of an allowlisted `symbol`. A symbol that is not on the
+    /// allowlist as given is matched through the same spellings as
+    /// `resolve_symbol`, so `xlm` or `XLM_USD` still reach an allowlisted
+    /// `XLM`. The check is local: the oracle is only called for a symbol that
+    /// passes it.
+    pub fn last_price(env: Env, symbol: Symbol) -> Result<i128, Error> {
+        ensure_not_paused(&env);
+        let symbol = if is_allowed(&env, &symbol) {
+            symbol
+        } else {
+            symbol_variants(&env, &symbol)
+                .into_iter()
+                .flatten()
+                .find(|candidate| is_allowed(&env, candidate))
+                .ok_or(Error::AssetMissing)?
+        };
+        last_price_of(&env, &ReflectorAsset::Other(symbol))
+    }
+
+    pub fn bounded_price(env: Env, symbol: Symbol) -> Result<i128, Error> {
+        let reflector_client = oracle_client(&env);
+        let asset = ReflectorAsset::Other(symbol.clone());
+        let price = current_price(&env, &reflector_client, &asset)
+            .and_then(|data| to_output_decimals(&env, &reflector_client, data.price))
+            .ok_or(Error::PriceUnavailable)?;
+
+        let key = DataKey::Bounds(symbol);
+        let bounds: Option<(i128, i128)> = env.storage().persistent().get(&key);
+        if let Some((min, max)) = bounds {
+            if price < min || price > max {
+                return Err(Error::OutOfBounds);
+            }
+        }
+        Ok(price)
+    }
+
+    pub fn require_price(env: Env, symbol: Symbol) -> i128 {
+        let reflector_client = oracle_client(&env);
+        let asset = ReflectorAsset::Other(symbol);
+        let price = current_price(&env, &reflector_client, &asset)
+            .and_then(|data| to_output_decimals(&env, &reflector_client, data.price));
+        match price {
+            Some(price) => price,
+            None => panic_with_error!(&env, Error::PriceUnavailable),
+        }
+    }
+
+    pub fn last_price_stellar(env: Env, token: Address) -> Result<i128, Error> {
+        last_price_of(&env, &ReflectorAsset::Stellar(token))
+    }
+
+    pub fn aliased_price(env: Env, symbol: Symbol) -> Option<i128> {
+        let key = DataKey::Alias(symbol.clone());
+        let alias: Option<Address> = env.storage().persistent().get(&key);
+        let asset = match alias {
+            Some(address) => ReflectorAsset::Stellar(address),
+            None => ReflectorAsset::Other(symbol),
+        };
+
+        let reflector_client = oracle_client(&env);
+        let price = current_price(&env, &reflector_client, &asset)?.price;
+        to_output_decimals(&env, &reflector_client, price)
+    }
+
+    /// Reads `symbol` from `oracle` instead of the configured oracle, so the
+    /// admin can compare feeds before switching with `set_oracle`.
+    pub fn last_price_from(
+        env: Env,
+        admin: Address,
+        oracle: Address,
+        symbol: Symbol,
+    ) -> Result<i128, Error> {
+        require_admin(&env, &admin)?;
+        ensure_not_paused(&env);
+
+        let reflector_client = ReflectorClient::new(&env, &oracle);
+        let asset = ReflectorAsset::Other(symbol);
+        match reflector_client.try_lastprice(&asset) {
+            Ok(Ok(Some(data))) => to_output_decimals(&env, &reflector_client, data.price)
+                .ok_or(Error::PriceUnavailable),
+            _ => Err(Error::PriceUnavailable),
+        }
+    }
+
+    /// Price of `symbol` from the configured oracle, or from the fallback
+    /// oracle when the primary has none and a fallback was deployed.
+    pub fn last_price_ha(env: Env, symbol: Symbol) -> Result<i128, Error> {
+        let asset = ReflectorAsset::Other(symbol);
+
+        let primary = oracle_client(&env);
+        let price = match current_price(&env, &primary, &asset) {
+            Some(data) => to_output_decimals(&env, &primary, data.price),
+            None => {
+                let fallback_oracle =
+                    fallback_oracle_address(&env).ok_or(Error::PriceUnavailable)?;
+                let fallback = ReflectorClient::new(&env, &fallback_oracle);
+                let data = read_price(&env, &fallback, &asset).ok_or(Error::PriceUnavailable)?;
+                to_output_decimals(&env, &fallback, data.price)
+            }
+        };
+        price.ok_or(Error::PriceUnavailable)
+    }
+
+    /// Price of `symbol` from the configured oracle, provided the fallback
+    /// oracle agrees within `max_bps`. Without a fallback there is nothing to
+    /// check against, so no price is returned.
+    pub fn checked_price(env: Env, symbol: Symbol, max_bps: u32) -> Result<i128, Error> {
+        let asset = ReflectorAsset::Other(symbol);
+
+        // Both feeds are compared in the output decimals, since the two
+        // oracles need not share a scale.
+        let reflector_client = oracle_client(&env);
+        let primary = read_price(&env, &reflector_client, &asset)
+            .and_then(|data| to_output_decimals(&env, &reflector_client, data.price))
+            .ok_or(Error::PriceUnavailable)?;
+        let fallback_oracle = fallback_oracle_address(&env).ok_or(Error::PriceUnavailable)?;
+        let fallback = ReflectorClient::new(&env, &fallback_oracle);
+        let secondary = read_price(&env, &fallback, &asset)
+            .and_then(|data| to_output_decimals(&env, &fallback, data.price))
+            .ok_or(Error::PriceUnavailable)?;
+
+        match deviation_bps(primary, secondary) {
+            Some(bps) if bps <= max_bps as i128 => Ok(primary),
+            _ => Err(Error::PriceDeviation),
+        }
+    }
+
+    pub fn median_price(env: Env, symbol: Symbol) -> Option<i128> {
+        let asset = ReflectorAsset::Other(symbol);
+
+        let mut prices = [0i128; 3];
+        let mut count = 0;
+        for oracle in oracle_addresses(&env).iter() {
+            // A feed that reverts is left out instead of aborting the median.
+            // Each is moved to the output decimals before they are compared.
+            let client = ReflectorClient::new(&env, &oracle);
+            if let Some(price) = read_price(&env, &client, &asset)
+                .and_then(|data| to_output_decimals(&env, &client, data.price))
+            {
+                prices[count] = price;
+                count += 1;
+            }
+        }
+
+        let prices = &mut prices[..count];
+        prices.sort_unstable();
+        let median = match prices {
+            [] => return None,
+            [price] => *price,
+            [low, high] => *low + (*high - *low) / 2,
+            _ => prices[count / 2],
+        };
+        Some(median)
+    }
+
+    /// Price of `symbol` combined across the oracle pool with the configured
+    /// `AggStrategy`. Each feed is read like any other price, so the base
+    /// asset is one unit and oracles that revert or have no price are
+    /// skipped. Feeds are combined in the output decimals, so oracles with
+    /// different scales can share a pool. The median of an even count is the
+    /// midpoint of the middle two.
+    pub fn agg_price(env: Env, symbol: Symbol) -> Option<i128> {
+        let asset = ReflectorAsset::Other(symbol);
+        let strategy: AggStrategy = env.storage().instance().get(&DataKey::Strategy).unwrap();
+
+        let mut prices = [0i128; MAX_BATCH_SIZE as usize];
+        let mut count = 0;
+        for oracle in aggregation_oracles(&env).iter() {
+            let client = ReflectorClient::new(&env, &oracle);
+            if let Some(price) = read_price(&env, &client, &asset)
+                .and_then(|data| to_output_decimals(&env, &client, data.price))
+            {
+                prices[count] = price;
+                count += 1;
+                if strategy == AggStrategy::First {
+                    break;
+                }
+            }
+        }
+
+        let prices = &mut prices[..count];
+        if prices.is_empty() {
+            return None;
+        }
+        prices.sort_unstable();
+        let price = match strategy {
+            AggStrategy::First => prices[0],
+            AggStrategy::Min => prices[0],
+            AggStrategy::Max => prices[count - 1],
+            AggStrategy::Mean => {
+                let sum = prices
+                    .iter()
+                    .try_fold(0i128, |sum, &price| sum.checked_add(price))?;
+                sum / count as i128
+            }
+            AggStrategy::Median if count % 2 == 0 => {
+                let (low, high) = (prices[count / 2 - 1], prices[count / 2]);
+                low + (high - low) / 2
+            }
+            AggStrategy::Median => prices[count / 2],
+        };
+        Some(price)
+    }
+
+    pub fn last_price_data(env: Env, symbol: Symbol) -> Option<PriceData> {
+        let reflector_client = oracle_client(&env);
+        let asset = ReflectorAsset::Other(symbol);
+        current_price(&env, &reflector_client, &asset)
+    }
+
+    /// Latest price of `symbol` together with the number of decimals it is
+    /// expressed in, so callers need no separate `decimals()` lookup.
+    pub fn last_price_scaled(env: Env, symbol: Symbol) -> Option<(i128, u32)> {
+        let reflector_client = oracle_client(&env);
+        let asset = ReflectorAsset::Other(symbol);
+        let price = current_price(&env, &reflector_client, &asset)?.price;
+        let price = to_output_decimals(&env, &reflector_client, price)?;
+        Some((price, output_decimals(&env)?))
+    }
+
+    /// Returns the price of `symbol` if it is no older than the stricter of
+    /// `max_age_secs` and the threshold set with `set_asset_max_age`. With
+    /// neither, the contract's default max age applies.
+    pub fn fresh_price(env: Env, symbol: Symbol, max_age_secs: Option<u64>) -> Result<i128, Error> {
+        let max_age_secs = resolve_max_age(&env, max_age_secs, asset_max_age(&env, &symbol));
+
+        let reflector_client = oracle_client(&env);
+        let asset = ReflectorAsset::Other(symbol.clone());
+        let price_data =
+            current_price(&env, &reflector_client, &asset).ok_or(Error::PriceUnavailable)?;
+
+        ensure_fresh(&env, &price_data, max_age_secs)?;
+        remember_good_price(&env, &symbol, &price_data);
+        to_output_decimals(&env, &reflector_client, price_data.price).ok_or(Error::PriceUnavailable)
+    }
+
+    /// Seconds since the price of `symbol` was published. A live
+    /// `set_manual_price` override is stamped with the ledger time, so it
+    /// always reads as 0.
+    pub fn price_age(env: Env, symbol: Symbol) -> Option<u64> {
+        let reflector_client = oracle_client(&env);
+        let asset = ReflectorAsset::Other(symbol);
+        let price_data = current_price(&env, &reflector_client, &asset)?;
+
+        Some(
+            env.ledger()
+                .timestamp()
+                .saturating_sub(price_data.timestamp),
+        )
+    }
+
+    /// Price of `symbol` with whether it is no older than the stricter of
+    /// `max_age_secs` and the `set_asset_max_age` threshold, or the contract
+    /// default with neither.
+    pub fn price_with_freshness(
+        env: Env,
+        symbol: Symbol,
+        max_age_secs: Option<u64>,
+    ) -> Option<(i128, bool)> {
+        let max_age_secs = resolve_max_age(&env, max_age_secs, asset_max_age(&env, &symbol));
+
+        let reflector_client = oracle_client(&env);
+        let asset = ReflectorAsset::Other(symbol.clone());
+        let price_data = current_price(&env, &reflector_client, &asset)?;
+
+        let is_fresh = ensure_fresh(&env, &price_data, max_age_secs).is_ok();
+        if is_fresh {
+            remember_good_price(&env, &symbol, &price_data);
+        }
+        let price = to_output_decimals(&env, &reflector_client, price_data.price)?;
+        Some((price, is_fresh))
+    }
+
+    /// Fresh price of `symbol` if the oracle has one, otherwise the last
+    /// price any freshness-checked getter accepted for it, however old. A
+    /// live `set_manual_price` override comes first, as `PriceSource::Manual`.
+    /// Freshness uses the stricter of `max_age_secs` and the
+    /// `set_asset_max_age` threshold, or the contract default with neither.
+    pub fn last_good_price(
+        env: Env,
+        symbol: Symbol,
+        max_age_secs: Option<u64>,
+    ) -> Option<(i128, PriceSource)> {
+        let max_age_secs = resolve_max_age(&env, max_age_secs, asset_max_age(&env, &symbol));
+
+        let reflector_client = oracle_client(&env);
+        let asset = ReflectorAsset::Other(symbol.clone());
+        if let Some(price_data) = manual_override(&env, &asset) {
+            let price = to_output_decimals(&env, &reflector_client, price_data.price)?;
+            return Some((price, PriceSource::Manual));
+        }
+
+        let live = read_price(&env, &reflector_client, &asset)
+            .filter(|data| ensure_fresh(&env, data, max_age_secs).is_ok());
+        let (price_data, source) = match live {
+            Some(data) => {
+                remember_good_price(&env, &symbol, &data);
+                (data, PriceSource::Live)
+            }
+            None => {
+                let key = DataKey::LastGood(symbol);
+                (env.storage().persistent().get(&key)?, PriceSource::LastGood)
+            }
+        };
+
+        let price = to_output_decimals(&env, &reflector_client, price_data.price)?;
+        Some((price, source))
+    }
+
+    pub fn normalized_price(env: Env, symbol: Symbol, target_decimals: u32) -> Option<i128> {
+        let reflector_client = oracle_client(&env);
+        let asset = ReflectorAsset::Other(symbol);
+        let price_data = current_price(&env, &reflector_client, &asset)?;
+
+        rescale(
+            price_data.price,
+            oracle_decimals(&env, &reflector_client)?,
+            target_decimals,
+        )
+    }
+
+    pub fn last_prices(env: Env, symbols: Vec<Symbol>) -> Result<Vec<i128>, Error> {
+        if symbols.len() > MAX_BATCH_SIZE {
+            return Err(Error::AssetLimitExceeded);
+        }
+
+        let reflector_client = oracle_client(&env);
+        let mut prices = Vec::new(&env);
+        for symbol in symbols.iter() {
+            let asset = ReflectorAsset::Other(symbol);
+            let price = current_price(&env, &reflector_client, &asset)
+                .and_then(|data| to_output_decimals(&env, &reflector_client, data.price))
+                .unwrap_or(-1);
+            prices.push_back(price);
+        }
+        Ok(prices)
+    }
+
+    pub fn bulk_snapshot(env: Env, symbols: Vec<Symbol>) -> Result<(Vec<i128>, Vec<u64>), Error> {
+        if symbols.len() > MAX_BATCH_SIZE {
+            return Err(Error::AssetLimitExceeded);
+        }
+
+        let reflector_client = oracle_client(&env);
+        let mut prices = Vec::new(&env);
+        let mut timestamps = Vec::new(&env);
+        for symbol in symbols.iter() {
+            let asset = ReflectorAsset::Other(symbol);
+            let (price, timestamp) = match current_price(&env, &reflector_client, &asset) {
+                Some(data) => (
+                    to_output_decimals(&env, &reflector_client, data.price).unwrap_or(-1),
+                    data.timestamp,
+                ),
+                None => (-1, 0),
+            };
+            prices.push_back(price);
+            timestamps.push_back(timestamp);
+        }
+        Ok((prices, timestamps))
+    }
+
+    pub fn basket_price(env: Env, symbols: Vec<Symbol>, weights_bps: Vec<u32>) -> Option<i128> {
+        if symbols.len() != weights_bps.len() || symbols.len() > MAX_BATCH_SIZE {
+            return None;
+        }
+        let total_weight = weights_bps
+            .iter()
+            .try_fold(0u32, |total, weight| total.checked_add(weight))?;
+        if total_weight != 10_000 {
+            return None;
+        }
+
+        let reflector_client = oracle_client(&env);
+        let mut weighted_sum = 0i128;
+        for (symbol, weight) in symbols.iter().zip(weights_bps.iter()) {
+            let asset = ReflectorAsset::Other(symbol);
+            let price_data = current_price(&env, &reflector_client, &asset)?;
+            let weighted = price_data.price.checked_mul(weight as i128)?;
+            weighted_sum = weighted_sum.checked_add(weighted)?;
+        }
+        to_output_decimals(&env, &reflector_client, weighted_sum / 10_000)
+    }
+
+    /// Equal-weight geometric mean of the constituents' prices, in the
+    /// output decimals. Unlike `basket_price`, a single expensive
+    /// asset does not dominate the index. `None` if any price is missing or
+    /// not positive.
+    pub fn geomean_index(env: Env, symbols: Vec<Symbol>) -> Option<i128> {
+        if symbols.len() > MAX_BATCH_SIZE {
+            return None;
+        }
+
+        let reflector_client = oracle_client(&env);
+        let mut prices = [0i128; MAX_BATCH_SIZE as usize];
+        for (i, symbol) in symbols.iter().enumerate() {
+            let asset = ReflectorAsset::Other(symbol);
+            prices[i] = current_price(&env, &reflector_client, &asset)?.price;
+        }
+        let index = geometric_mean(&prices[..symbols.len() as usize])?;
+        to_output_decimals(&env, &reflector_client, index)
+    }
+
+    /// Values `amount` of `symbol` in the oracle base asset, rounding with
+    /// `rounding`. When omitted the value is truncated towards zero, so a
+    /// negative amount is valued as the exact negation of its positive.
+    pub fn value_of(
+        env: Env,
+        symbol: Symbol,
+        amount: i128,
+        rounding: Option<Rounding>,
+    ) -> Option<i128> {
+        if amount == 0 {
+            return Some(0);
+        }
+
+        let reflector_client = oracle_client(&env);
+        let asset = ReflectorAsset::Other(symbol);
+        let price_data = current_price(&env, &reflector_client, &asset)?;
+
+        let scale = 10i128.checked_pow(oracle_decimals(&env, &reflector_client)?)?;
+        mul_div_round(
+            amount,
+            price_data.price,
+            scale,
+            rounding.unwrap_or(Rounding::Trunc),
+        )
+    }
+
+    /// Value in the oracle base of a two-sided position, each leg valued as
+    /// `value_of` does with its default `Trunc` rounding. Both prices must be
+    /// available even when an amount is zero.
+    pub fn lp_value(
+        env: Env,
+        token_a: Symbol,
+        amount_a: i128,
+        token_b: Symbol,
+        amount_b: i128,
+    ) -> Option<i128> {
+        let reflector_client = oracle_client(&env);
+        let price_a =
+            current_price(&env, &reflector_client, &ReflectorAsset::Other(token_a))?.price;
+        let price_b =
+            current_price(&env, &reflector_client, &ReflectorAsset::Other(token_b))?.price;
+
+        let scale = 10i128.checked_pow(oracle_decimals(&env, &reflector_client)?)?;
+        let value_a = mul_div_round(amount_a, price_a, scale, Rounding::Trunc)?;
+        let value_b = mul_div_round(amount_b, price_b, scale, Rounding::Trunc)?;
+        value_a.checked_add(value_b)
+    }
+
+    /// Converts `amount` of `from` into units of `to` through the oracle base.
+    /// Both prices share the oracle's decimals, so the scales cancel and the
+    /// result keeps the precision of `amount`. The final division rounds with
+    /// `rounding`, or truncates towards zero when omitted.
+    pub fn convert(
+        env: Env,
+        from: Symbol,
+        to: Symbol,
+        amount: i128,
+        rounding: Option<Rounding>,
+    ) -> Option<i128> {
+        if amount == 0 {
+            return Some(0);
+        }
+
+        let reflector_client = oracle_client(&env);
+        let from_price =
+            current_price(&env, &reflector_client, &ReflectorAsset::Other(from))?.price;
+        let to_price = current_price(&env, &reflector_client, &ReflectorAsset::Other(to))?.price;
+        if to_price == 0 {
+            return None;
+        }
+
+        mul_div_round(
+            amount,
+            from_price,
+            to_price,
+            rounding.unwrap_or(Rounding::Trunc),
+        )
+    }
+
+    pub fn observe(env: Env, symbol: Symbol) -> Result<PriceData, Error> {
+        let reflector_client = oracle_client(&env);
+        let asset = ReflectorAsset::Other(symbol.clone());
+        let price_data =
+            read_price(&env, &reflector_client, &asset).ok_or(Error::PriceUnavailable)?;
+
+        let storage = env.storage().persistent();
+        let high_key = DataKey::High(symbol.clone());
+        let low_key = DataKey::Low(symbol);
+
+        let high: Option<i128> = storage.get(&high_key);
+        if high.is_none_or(|high| price_data.price > high) {
+            storage.set(&high_key, &price_data.price);
+        }
+        let low: Option<i128> = storage.get(&low_key);
+        if low.is_none_or(|low| price_data.price < low) {
+            storage.set(&low_key, &price_data.price);
+        }
+        for key in [high_key, low_key] {
+            storage.extend_ttl(&key, PERSISTENT_LIFETIME_THRESHOLD, PERSISTENT_BUMP_AMOUNT);
+        }
+
+        Ok(price_data)
+    }
+
+    pub fn record_price(env: Env, symbol: Symbol) -> Result<(), Error> {
+        let reflector_client = oracle_client(&env);
+        let asset = ReflectorAsset::Other(symbol.clone());
+        let price_data =
+            read_price(&env, &reflector_client, &asset).ok_or(Error::PriceUnavailable)?;
+
+        let key = DataKey::History(symbol);
+        let mut history: PriceHistory =
+            env.storage()
+                .persistent()
+                .get(&key)
+                .unwrap_or_else(|| PriceHistory {
+                    entries: Vec::new(&env),
+                    next: 0,
+                });
+
+        // Once the buffer is full, `next` points at the oldest entry.
+        if history.entries.len() < HISTORY_SIZE {
+            history.entries.push_back(price_data);
+        } else {
+            history.entries.set(history.next, price_data);
+        }
+        history.next = (history.next + 1) % HISTORY_SIZE;
+
+        env.storage().persistent().set(&key, &history);
+        env.storage().persistent().extend_ttl(
+            &key,
+            PERSISTENT_LIFETIME_THRESHOLD,
+            PERSISTENT_BUMP_AMOUNT,
+        );
+        Ok(())
+    }
+
+    /// Prices stored by `record_price`, oldest first.
+    pub fn history(env: Env, symbol: Symbol) -> Vec<PriceData> {
+        ensure_not_paused(&env);
+        let key = DataKey::History(symbol);
+        let history: Option<PriceHistory> = env.storage().persistent().get(&key);
+        let Some(history) = history else {
+            return Vec::new(&env);
+        };
+
+        if history.entries.len() < HISTORY_SIZE {
+            return history.entries;
         }
+        let mut ordered = history.entries.slice(history.next..);
+        ordered.append(&history.entries.slice(..history.next));
+        ordered
+    }
+
+    /// Publishes a `price_update` event when `symbol` has moved more than
+    /// `min_change_bps` since the last one, so keepers can index changes
+    /// instead of polling. The first poll always publishes. Returns whether
+    /// an event went out.
+    pub fn poll_and_emit(env: Env, symbol: Symbol, min_change_bps: u32) -> Result<bool, Error> {
+        let reflector_client = oracle_client(&env);
+        let asset = ReflectorAsset::Other(symbol.clone());
+        let price_data =
+            read_price(&env, &reflector_client, &asset).ok_or(Error::PriceUnavailable)?;
+
+        let key = DataKey::LastEmitted(symbol.clone());
+        let last: Option<PriceData> = env.storage().persistent().get(&key);
+        let changed = match last {
+            Some(last) => deviation_bps(last.price, price_data.price)
+                .is_none_or(|bps| bps > min_change_bps as i128),
+            None => true,
+        };
+        if !changed {
+            return Ok(false);
+        }
+
+        env.storage().persistent().set(&key, &price_data);
+        env.storage().persistent().extend_ttl(
+            &key,
+            PERSISTENT_LIFETIME_THRESHOLD,
+            PERSISTENT_BUMP_AMOUNT,
+        );
+        env.events()
+            .publish((Symbol::new(&env, "price_update"), symbol), price_data);
+        Ok(true)
+    }
+
+    /// Freezes the current price of `symbol` as the settlement price of
+    /// `round_id`. A round can only be locked once.
+    pub fn lock_price(
+        env: Env,
+        admin: Address,
+        symbol: Symbol,
+        round_id: u64,
+    ) -> Result<i128, Error> {
+        require_admin(&env, &admin)?;
+
+        let key = DataKey::Locked(symbol.clone(), round_id);
+        if env.storage().persistent().has(&key) {
+            return Err(Error::AssetAlreadyExists);
+        }
+
+        let reflector_client = oracle_client(&env);
+        let asset = ReflectorAsset::Other(symbol);
+        let price = read_price(&env, &reflector_client, &asset)
+            .ok_or(Error::PriceUnavailable)?
+            .price;
+
+        env.storage().persistent().set(&key, &price);
+        env.storage().persistent().extend_ttl(
+            &key,
+            PERSISTENT_LIFETIME_THRESHOLD,
+            PERSISTENT_BUMP_AMOUNT,
+        );
+        Ok(price)
+    }
+
+    /// Settlement price stored by `lock_price`, in the oracle's own decimals.
+    pub fn locked_price(env: Env, symbol: Symbol, round_id: u64) -> Option<i128> {
+        ensure_not_paused(&env);
+        env.storage()
+            .persistent()
+            .get(&DataKey::Locked(symbol, round_id))
+    }
+
+    pub fn high_watermark(env: Env, symbol: Symbol) -> Option<i128> {
+        ensure_not_paused(&env);
+        env.storage().persistent().get(&DataKey::High(symbol))
+    }
+
+    pub fn low_watermark(env: Env, symbol: Symbol) -> Option<i128> {
+        ensure_not_paused(&env);
+        env.storage().persistent().get(&DataKey::Low(symbol))
+    }
+
+    pub fn quote(env: Env, symbol: Symbol, slippage_bps: u32) -> Option<(i128, i128, i128)> {
+        if slippage_bps > 10_000 {
+            return None;
+        }
+
+        let reflector_client = oracle_client(&env);
+        let asset = ReflectorAsset::Other(symbol);
+        let price = current_price(&env, &reflector_client, &asset)?.price;
+        let price = to_output_decimals(&env, &reflector_client, price)?;
+
+        let min = apply_bps(price, 10_000 - slippage_bps)?;
+        let max = apply_bps(price, 10_000 + slippage_bps)?;
+        Some((price, min, max))
+    }
+
+    /// Returns `-1`, `0` or `1` as the price of `a` is below, equal to or
+    /// above that of `b`. Both prices come from the same oracle, so they
+    /// share its base and decimals and compare directly.
+    pub fn compare(env: Env, a: Symbol, b: Symbol) -> Option<i32> {
+        let reflector_client = oracle_client(&env);
+        let a_price = current_price(&env, &reflector_client, &ReflectorAsset::Other(a))?.price;
+        let b_price = current_price(&env, &reflector_client, &ReflectorAsset::Other(b))?.price;
+
+        Some(a_price.cmp(&b_price) as i32)
+    }
+
+    pub fn inverse_price(env: Env, symbol: Symbol) -> Option<i128> {
+        let reflector_client = oracle_client(&env);
+        let asset = ReflectorAsset::Other(symbol);
+        let price_data = current_price(&env, &reflector_client, &asset)?;
+
+        let inverted = invert(price_data.price, oracle_decimals(&env, &reflector_client)?)?;
+        to_output_decimals(&env, &reflector_client, inverted)
+    }
+
+    /// Whether the price of `symbol` is above `threshold`, given in the
+    /// output decimals.
+    pub fn is_above(env: Env, symbol: Symbol, threshold: i128) -> Option<bool> {
+        let reflector_client = oracle_client(&env);
+        let asset = ReflectorAsset::Other(symbol);
+        let price = current_price(&env, &reflector_client, &asset)?.price;
+        Some(to_output_decimals(&env, &reflector_client, price)? > threshold)
+    }
+
+    /// Whether the price of `symbol` is below `threshold`, given in the
+    /// output decimals.
+    pub fn is_below(env: Env, symbol: Symbol, threshold: i128) -> Option<bool> {
+        let reflector_client = oracle_client(&env);
+        let asset = ReflectorAsset::Other(symbol);
+        let price = current_price(&env, &reflector_client, &asset)?.price;
+        Some(to_output_decimals(&env, &reflector_client, price)? < threshold)
+    }
+
+    pub fn net_price(env: Env, symbol: Symbol, fee_bps: u32) -> Option<i128> {
+        if fee_bps > 10_000 {
+            return None;
+        }
+
+        let reflector_client = oracle_client(&env);
+        let asset = ReflectorAsset::Other(symbol);
+        let price = current_price(&env, &reflector_client, &asset)?.price;
+        let price = to_output_decimals(&env, &reflector_client, price)?;
+        apply_bps(price, 10_000 - fee_bps)
+    }
+
+    pub fn cross_price(env: Env, base: Symbol, quote: Symbol) -> Option<i128> {
+        let reflector_client = oracle_client(&env);
+        let base_asset = ReflectorAsset::Other(base);
+        let quote_asset = ReflectorAsset::Other(quote);
+
+        let price = read_cross_price(&env, &reflector_client, &base_asset, &quote_asset)?.price;
+        to_output_decimals(&env, &reflector_client, price)
+    }
+
+    pub fn has_cross(env: Env, base: Symbol, quote: Symbol) -> bool {
+        let reflector_client = oracle_client(&env);
+        let base_asset = ReflectorAsset::Other(base);
+        let quote_asset = ReflectorAsset::Other(quote);
+        reflector_client
+            .x_last_price(&base_asset, &quote_asset)
+            .is_some()
+    }
+
+    /// Price of `base` in the contract's configured denomination.
+    pub fn denominated_price(env: Env, base: Symbol) -> Option<i128> {
+        let denomination: Symbol = env
+            .storage()
+            .instance()
+            .get(&DataKey::Denomination)
+            .unwrap();
+
+        let reflector_client = oracle_client(&env);
+        let base_asset = ReflectorAsset::Other(base);
+        let quote_asset = ReflectorAsset::Other(denomination);
+        let price = read_cross_price(&env, &reflector_client, &base_asset, &quote_asset)?.price;
+        to_output_decimals(&env, &reflector_client, price)
+    }
+
+    pub fn any_cross(env: Env, a: Symbol, b: Symbol) -> Option<i128> {
+        let reflector_client = oracle_client(&env);
+        let a_asset = ReflectorAsset::Other(a);
+        let b_asset = ReflectorAsset::Other(b);
+
+        let price = match read_cross_price(&env, &reflector_client, &a_asset, &b_asset) {
+            Some(data) => data.price,
+            None => {
+                let reverse = read_cross_price(&env, &reflector_client, &b_asset, &a_asset)?;
+                invert(reverse.price, oracle_decimals(&env, &reflector_client)?)?
+            }
+        };
+        to_output_decimals(&env, &reflector_client, price)
+    }
+
+    pub fn prices_in(env: Env, bases: Vec<Symbol>, quote: Symbol) -> Result<Vec<i128>, Error> {
+        if bases.len() > MAX_BATCH_SIZE {
+            return Err(Error::AssetLimitExceeded);
+        }
+
+        let reflector_client = oracle_client(&env);
+        let quote_asset = ReflectorAsset::Other(quote);
+        let mut prices = Vec::new(&env);
+        for base in bases.iter() {
+            let base_asset = ReflectorAsset::Other(base);
+            let price = read_cross_price(&env, &reflector_client, &base_asset, &quote_asset)
+                .and_then(|data| to_output_decimals(&env, &reflector_client, data.price))
+                .unwrap_or(-1);
+            prices.push_back(price);
+        }
+        Ok(prices)
+    }
+
+    pub fn derived_cross(env: Env, base: Symbol, quote: Symbol) -> Option<i128> {
+        let reflector_client = oracle_client(&env);
+        derive_cross(
+            &env,
+            &reflector_client,
+            &ReflectorAsset::Other(base),
+            &ReflectorAsset::Other(quote),
+        )
+    }
+
+    /// Returns `(base_price, quote_price, cross_rate)` so the derived rate can
+    /// be checked against the two legs it was computed from.
+    pub fn cross_breakdown(env: Env, base: Symbol, quote: Symbol) -> Option<(i128, i128, i128)> {
+        let reflector_client = oracle_client(&env);
+        let base_price =
+            current_price(&env, &reflector_client, &ReflectorAsset::Other(base))?.price;
+        let quote_price =
+            current_price(&env, &reflector_client, &ReflectorAsset::Other(quote))?.price;
+
+        let rate = cross_rate(
+            base_price,
+            quote_price,
+            oracle_decimals(&env, &reflector_client)?,
+        )?;
+        Some((
+            to_output_decimals(&env, &reflector_client, base_price)?,
+            to_output_decimals(&env, &reflector_client, quote_price)?,
+            to_output_decimals(&env, &reflector_client, rate)?,
+        ))
+    }
+
+    pub fn price_in_xlm(env: Env, symbol: Symbol) -> Option<i128> {
+        let reflector_client = oracle_client(&env);
+        let asset = ReflectorAsset::Other(symbol);
+        let xlm_asset = ReflectorAsset::Other(Symbol::new(&env, "XLM"));
+
+        if let Some(data) = read_cross_price(&env, &reflector_client, &asset, &xlm_asset) {
+            return to_output_decimals(&env, &reflector_client, data.price);
+        }
+        derive_cross(&env, &reflector_client, &asset, &xlm_asset)
+    }
+
+    /// Reads the price recorded at `timestamp`. The oracle stores one record
+    /// per `resolution()` seconds, so `timestamp` should sit on a resolution
+    /// boundary; use `align_timestamp` to floor an arbitrary time.
+    pub fn price_at(env: Env, symbol: Symbol, timestamp: u64) -> Option<i128> {
+        let reflector_client = oracle_client(&env);
+        let asset = ReflectorAsset::Other(symbol);
+        let price = read_price_at(&env, &reflector_client, &asset, timestamp)?.price;
+        to_output_decimals(&env, &reflector_client, price)
+    }
+
+    /// Like `price_at`, but when the record at `timestamp` is missing steps
+    /// back one `resolution()` at a time, at most `max_steps` times (capped
+    /// at `MAX_BATCH_SIZE`), and returns the most recent price found.
+    pub fn price_at_or_before(
+        env: Env,
+        symbol: Symbol,
+        timestamp: u64,
+        max_steps: u32,
+    ) -> Option<i128> {
+        let reflector_client = oracle_client(&env);
+        let asset = ReflectorAsset::Other(symbol);
+        let resolution = reflector_client.resolution();
+
+        let mut timestamp = align_to_resolution(timestamp, resolution);
+        for _ in 0..=max_steps.min(MAX_BATCH_SIZE) {
+            if let Some(data) = read_price_at(&env, &reflector_client, &asset, timestamp) {
+                return to_output_decimals(&env, &reflector_client, data.price);
+            }
+            timestamp = timestamp.checked_sub(resolution as u64)?;
+        }
+        None
+    }
+
+    /// Historical prices of `symbol` at each of `timestamps`, aligned down to
+    /// the oracle resolution, in input order with `-1` for missing records.
+    pub fn prices_at(env: Env, symbol: Symbol, timestamps: Vec<u64>) -> Result<Vec<i128>, Error> {
+        if timestamps.len() > MAX_BATCH_SIZE {
+            return Err(Error::AssetLimitExceeded);
+        }
+
+        let reflector_client = oracle_client(&env);
+        let asset = ReflectorAsset::Other(symbol);
+        let resolution = reflector_client.resolution();
+
+        let mut prices = Vec::new(&env);
+        for timestamp in timestamps.iter() {
+            let timestamp = align_to_resolution(timestamp, resolution);
+            let price = read_price_at(&env, &reflector_client, &asset, timestamp)
+                .and_then(|data| to_output_decimals(&env, &reflector_client, data.price))
+                .unwrap_or(-1);
+            prices.push_back(price);
+        }
+        Ok(prices)
+    }
+
+    pub fn align_timestamp(env: Env, timestamp: u64) -> u64 {
+        let reflector_client = oracle_client(&env);
+        align_to_resolution(timestamp, reflector_client.resolution())
+    }
+
+    pub fn price_series(env: Env, symbol: Symbol, records: u32) -> Vec<PriceData> {
+        let reflector_client = oracle_client(&env);
+        let asset = ReflectorAsset::Other(symbol);
+        reflector_client
+            .prices(&asset, &records)
+            .unwrap_or_else(|| Vec::new(&env))
+    }
+
+    /// Latest price of `symbol`, served from a per-symbol cache for up to the
+    /// stricter of `max_age_secs` and the cache TTL: the one set with
+    /// `set_cache_ttl`, else `set_default_cache_ttl`, else the contract max
+    /// age. A cached price is also never served once the oracle timestamp it
+    /// carries is older than the max age `fresh_price` would accept.
+    pub fn cached_price(
+        env: Env,
+        symbol: Symbol,
+        max_age_secs: Option<u64>,
+    ) -> Result<i128, Error> {
+        let cache_ttl = env
+            .storage()
+            .persistent()
+            .get(&DataKey::CacheTtl(symbol.clone()))
+            .or_else(|| env.storage().instance().get(&DataKey::DefaultCacheTtl));
+        let max_age_secs = resolve_max_age(&env, max_age_secs, cache_ttl);
+        let staleness_secs = resolve_max_age(&env, None, asset_max_age(&env, &symbol));
+
+        let reflector_client = oracle_client(&env);
+        let asset = ReflectorAsset::Other(symbol.clone());
+        // An override is served as it stands and never cached.
+        if let Some(price_data) = manual_override(&env, &asset) {
+            return to_output_decimals(&env, &reflector_client, price_data.price)
+                .ok_or(Error::PriceUnavailable);
+        }
+
+        let key = DataKey::Cache(symbol);
+        let now = env.ledger().timestamp();
+
+        let cached: Option<CachedPrice> = env.storage().persistent().get(&key);
+        if let Some(cached) = cached {
+            if now.saturating_sub(cached.fetched_at) <= max_age_secs
+                && ensure_fresh(&env, &cached.price_data, staleness_secs).is_ok()
+            {
+                publish_price(&env, &asset, &cached.price_data);
+                return to_output_decimals(&env, &reflector_client, cached.price_data.price)
+                    .ok_or(Error::PriceUnavailable);
+            }
+        }
+
+        let price_data =
+            read_price(&env, &reflector_client, &asset).ok_or(Error::PriceUnavailable)?;
+
+        let price = price_data.price;
+        env.storage().persistent().set(
+            &key,
+            &CachedPrice {
+                price_data,
+                fetched_at: now,
+            },
+        );
+        env.storage().persistent().extend_ttl(
+            &key,
+            PERSISTENT_LIFETIME_THRESHOLD,
+            PERSISTENT_BUMP_AMOUNT,
+        );
+        env.storage()
+            .instance()
+            .extend_ttl(INSTANCE_LIFETIME_THRESHOLD, INSTANCE_BUMP_AMOUNT);
+        to_output_decimals(&env, &reflector_client, price).ok_or(Error::PriceUnavailable)
+    }
+
+    /// Maps a loosely written ticker such as `xlm` or `XLM_USD` to the symbol
+    /// the oracle actually lists, or `None` if no variant is supported.
+    pub fn resolve_symbol(env: Env, input: Symbol) -> Option<Symbol> {
+        let supported = oracle_client(&env).assets();
+        symbol_variants(&env, &input)
+            .into_iter()
+            .flatten()
+            .find(|candidate| supported.contains(ReflectorAsset::Other(candidate.clone())))
+    }
+
+    pub fn supported_assets(env: Env) -> Vec<ReflectorAsset> {
+        oracle_client(&env).assets()
+    }
+
+    /// Number of assets the oracle supports. The oracle still returns the
+    /// full list to this contract; only the caller is spared from it.
+    pub fn asset_count(env: Env) -> u32 {
+        oracle_client(&env).assets().len()
+    }
+
+    pub fn base_asset(env: Env) -> ReflectorAsset {
+        oracle_client(&env).base()
+    }
+
+    /// Returns `(spot, twap, timestamp)` for `symbol`, where `timestamp`
+    /// belongs to the spot price. A missing TWAP is reported as `0` so the
+    /// spot is still returned; a missing spot yields `None`.
+    pub fn snapshot(env: Env, symbol: Symbol, twap_records: u32) -> Option<(i128, i128, u64)> {
+        let reflector_client = oracle_client(&env);
+        let asset = ReflectorAsset::Other(symbol);
+        let spot = current_price(&env, &reflector_client, &asset)?;
+        let twap = match twap_records {
+            0 => None,
+            records => read_twap(&env, &reflector_client, &asset, records),
+        };
+
+        let spot_price = to_output_decimals(&env, &reflector_client, spot.price)?;
+        let twap = twap
+            .and_then(|twap| to_output_decimals(&env, &reflector_client, twap))
+            .unwrap_or(0);
+        Some((spot_price, twap, spot.timestamp))
+    }
+
+    pub fn cross_twaps(
+        env: Env,
+        pairs: Vec<(Symbol, Symbol)>,
+        records: u32,
+    ) -> Result<Vec<i128>, Error> {
+        if records == 0 {
+            return Err(Error::PriceUnavailable);
+        }
+        if pairs.len() > MAX_BATCH_SIZE {
+            return Err(Error::AssetLimitExceeded);
+        }
+
+        let reflector_client = oracle_client(&env);
+        let mut twaps = Vec::new(&env);
+        for (base, quote) in pairs.iter() {
+            let base_asset = ReflectorAsset::Other(base);
+            let quote_asset = ReflectorAsset::Other(quote);
+            let twap = read_cross_twap(&env, &reflector_client, &base_asset, &quote_asset, records)
+                .and_then(|twap| to_output_decimals(&env, &reflector_client, twap))
+                .unwrap_or(-1);
+            twaps.push_back(twap);
+        }
+        Ok(twaps)
+    }
+
+    /// Short-window TWAP as basis points of the long-window TWAP, so `10_000`
+    /// means both windows agree and distance from it signals volatility.
+    pub fn volatility_proxy(
+        env: Env,
+        symbol: Symbol,
+        short_records: u32,
+        long_records: u32,
+    ) -> Option<i128> {
+        if short_records == 0 || short_records >= long_records {
+            return None;
+        }
+
+        let reflector_client = oracle_client(&env);
+        let asset = ReflectorAsset::Other(symbol);
+        let short_twap = read_twap(&env, &reflector_client, &asset, short_records)?;
+        let long_twap = read_twap(&env, &reflector_client, &asset, long_records)?;
+        if long_twap == 0 {
+            return None;
+        }
+
+        checked_mul_div(short_twap, 10_000, long_twap)
+    }
+
+    pub fn cross_twap(env: Env, base: Symbol, quote: Symbol, records: u32) -> Option<i128> {
+        if records == 0 {
+            return None;
+        }
+
+        let reflector_client = oracle_client(&env);
+        let base_asset = ReflectorAsset::Other(base);
+        let quote_asset = ReflectorAsset::Other(quote);
+        let twap = read_cross_twap(&env, &reflector_client, &base_asset, &quote_asset, records)?;
+        to_output_decimals(&env, &reflector_client, twap)
+    }
+
+    /// Cross TWAP of `base` in `quote`, taken from the oracle's own cross
+    /// TWAP when it has one and otherwise derived from each leg's TWAP in the
+    /// oracle base.
+    pub fn robust_cross(env: Env, base: Symbol, quote: Symbol, records: u32) -> Option<i128> {
+        if records == 0 {
+            return None;
+        }
+
+        let reflector_client = oracle_client(&env);
+        let base_asset = ReflectorAsset::Other(base);
+        let quote_asset = ReflectorAsset::Other(quote);
+        let twap =
+            match read_cross_twap(&env, &reflector_client, &base_asset, &quote_asset, records) {
+                Some(twap) => twap,
+                None => {
+                    let base_twap = leg_twap(&env, &reflector_client, &base_asset, records)?;
+                    let quote_twap = leg_twap(&env, &reflector_client, &quote_asset, records)?;
+                    cross_rate(
+                        base_twap,
+                        quote_twap,
+                        oracle_decimals(&env, &reflector_client)?,
+                    )?
+                }
+            };
+        to_output_decimals(&env, &reflector_client, twap)
+    }
+
+    pub fn safe_spot(env: Env, symbol: Symbol, records: u32, max_bps: u32) -> Result<i128, Error> {
+        if records == 0 {
+            return Err(Error::PriceUnavailable);
+        }
+
+        let reflector_client = oracle_client(&env);
+        let asset = ReflectorAsset::Other(symbol);
+        let spot = current_price(&env, &reflector_client, &asset).ok_or(Error::PriceUnavailable)?;
+        let twap =
+            read_twap(&env, &reflector_client, &asset, records).ok_or(Error::PriceUnavailable)?;
+
+        match deviation_bps(twap, spot.price) {
+            Some(bps) if bps <= max_bps as i128 => {
+                to_output_decimals(&env, &reflector_client, spot.price)
+                    .ok_or(Error::PriceUnavailable)
+            }
+            _ => Err(Error::PriceDeviation),
+        }
+    }
+
+    pub fn oracle_info(env: Env) -> (u32, u64, u32) {
+        let reflector_client = oracle_client(&env);
+        (
+            reflector_client.version(),
+            reflector_client.last_timestamp(),
+            reflector_client.resolution(),
+        )
+    }
+
+    pub fn oracle_admin(env: Env) -> Option<Address> {
+        oracle_client(&env).admin()
+    }
+
+    pub fn timing(env: Env) -> (u32, Option<u64>) {
+        let reflector_client = oracle_client(&env);
+        (reflector_client.resolution(), reflector_client.period())
+    }
+
+    pub fn assert_oracle_live(env: Env, max_silence_secs: u64) -> Result<(), Error> {
+        let reflector_client = oracle_client(&env);
+        let last_timestamp = reflector_client.last_timestamp();
+
+        // An oracle timestamp ahead of the ledger clock counts as live.
+        let silence = env.ledger().timestamp().saturating_sub(last_timestamp);
+        if silence > max_silence_secs {
+            return Err(Error::InvalidTimestamp);
+        }
+        Ok(())
+    }
+
+    pub fn mean_price(env: Env, symbol: Symbol, records: u32) -> Option<i128> {
+        let reflector_client = oracle_client(&env);
+        let asset = ReflectorAsset::Other(symbol);
+        let history = reflector_client.prices(&asset, &records)?;
+        if history.is_empty() {
+            return None;
+        }
+
+        let sum = history
+            .iter()
+            .try_fold(0i128, |sum, data| sum.checked_add(data.price))?;
+        to_output_decimals(&env, &reflector_client, sum / history.len() as i128)
+    }
+
+    pub fn spread_bps(env: Env, symbol: Symbol, records: u32) -> Option<i128> {
+        let reflector_client = oracle_client(&env);
+        let asset = ReflectorAsset::Other(symbol);
+        let history = reflector_client.prices(&asset, &records)?;
+
+        let min = history.iter().map(|data| data.price).min()?;
+        let max = history.iter().map(|data| data.price).max()?;
+        change_bps(min, max)
+    }
+
+    /// Largest gap in seconds between consecutive records in the window. A
+    /// gap well above `resolution()` means the feed skipped updates.
+    pub fn max_gap_secs(env: Env, symbol: Symbol, records: u32) -> Option<u64> {
+        let reflector_client = oracle_client(&env);
+        let asset = ReflectorAsset::Other(symbol);
+        let history = reflector_client.prices(&asset, &records)?;
+        if history.len() < 2 {
+            return None;
+        }
+
+        let mut max_gap = 0;
+        for i in 1..history.len() {
+            let newer = history.get_unchecked(i - 1).timestamp;
+            let older = history.get_unchecked(i).timestamp;
+            max_gap = max_gap.max(newer.abs_diff(older));
+        }
+        Some(max_gap)
+    }
+
+    /// Largest peak-to-trough decline in basis points of the peak over the
+    /// window, or `0` if the price never fell below an earlier high.
+    pub fn max_drawdown_bps(env: Env, symbol: Symbol, records: u32) -> Option<i128> {
+        let reflector_client = oracle_client(&env);
+        let asset = ReflectorAsset::Other(symbol);
+        let history = reflector_client.prices(&asset, &records)?;
+        if history.len() < 2 {
+            return None;
+        }
+
+        // The oracle lists the newest record first; walk it oldest first so
+        // the running peak only ever precedes the trough it is measured to.
+        let mut peak = history.last()?.price;
+        let mut max_drawdown = 0;
+        for data in history.iter().rev() {
+            if data.price > peak {
+                peak = data.price;
+            } else if peak > 0 {
+                let drawdown = checked_mul_div(peak.checked_sub(data.price)?, 10_000, peak)?;
+                max_drawdown = max_drawdown.max(drawdown);
+            }
+        }
+        Some(max_drawdown)
+    }
+
+    /// Whether any step between consecutive records moves more than
+    /// `max_step_bps` of the earlier price. A step that cannot be expressed,
+    /// such as one from a zero price, counts as a spike.
+    pub fn has_spike(env: Env, symbol: Symbol, records: u32, max_step_bps: u32) -> Option<bool> {
+        let reflector_client = oracle_client(&env);
+        let asset = ReflectorAsset::Other(symbol);
+        let history = reflector_client.prices(&asset, &records)?;
+        if history.len() < 2 {
+            return None;
+        }
+
+        for i in 1..history.len() {
+            let newer = history.get_unchecked(i - 1).price;
+            let older = history.get_unchecked(i).price;
+            match deviation_bps(older, newer) {
+                Some(bps) if bps <= max_step_bps as i128 => {}
+                _ => return Some(true),
+            }
+        }
+        Some(false)
+    }
+
+    /// Basis-point return of each record over the one before it, oldest
+    /// step first, so the result has one entry fewer than the window. Steps
+    /// from a zero price are reported as `0`.
+    pub fn returns_bps(env: Env, symbol: Symbol, records: u32) -> Vec<i128> {
+        let reflector_client = oracle_client(&env);
+        let asset = ReflectorAsset::Other(symbol);
+        let mut returns = Vec::new(&env);
+        let Some(history) = reflector_client.prices(&asset, &records) else {
+            return returns;
+        };
+
+        // The oracle lists the newest record first, so walk it backwards.
+        for i in (1..history.len()).rev() {
+            let older = history.get_unchecked(i).price;
+            let newer = history.get_unchecked(i - 1).price;
+            returns.push_back(change_bps(older, newer).unwrap_or(0));
+        }
+        returns
+    }
+
+    pub fn confident_price(env: Env, symbol: Symbol, min_records: u32) -> Option<i128> {
+        let reflector_client = oracle_client(&env);
+        let asset = ReflectorAsset::Other(symbol);
+        let history = reflector_client.prices(&asset, &min_records)?;
+        if history.len() < min_records {
+            return None;
+        }
+
+        let price = current_price(&env, &reflector_client, &asset)?.price;
+        to_output_decimals(&env, &reflector_client, price)
+    }
+
+    /// Mean of the records with `timestamp >= since_ts`. The oracle can only
+    /// be queried by record count, so this fetches enough records to cover
+    /// the window, up to `MAX_LOOKBACK_RECORDS`; older records are out of
+    /// reach, as is anything past the oracle's own retention.
+    pub fn avg_since(env: Env, symbol: Symbol, since_ts: u64) -> Option<i128> {
+        let reflector_client = oracle_client(&env);
+        let asset = ReflectorAsset::Other(symbol);
+
+        let resolution = reflector_client.resolution().max(1) as u64;
+        let span = env.ledger().timestamp().saturating_sub(since_ts);
+        let records = (span / resolution + 1).min(MAX_LOOKBACK_RECORDS as u64) as u32;
+        let history = reflector_client.prices(&asset, &records)?;
+
+        let mut sum = 0i128;
+        let mut count = 0i128;
+        for data in history.iter().filter(|data| data.timestamp >= since_ts) {
+            sum = sum.checked_add(data.price)?;
+            count += 1;
+        }
+        if count == 0 {
+            return None;
+        }
+        to_output_decimals(&env, &reflector_client, sum / count)
+    }
+
+    pub fn price_change_bps(env: Env, symbol: Symbol, records_ago: u32) -> Option<i128> {
+        let reflector_client = oracle_client(&env);
+        let asset = ReflectorAsset::Other(symbol);
+        let current = current_price(&env, &reflector_client, &asset)?;
+
+        // The oracle returns the newest record first, so the oldest is last.
+        let history = reflector_client.prices(&asset, &records_ago)?;
+        let past = history.last()?;
+
+        change_bps(past.price, current.price)
+    }
+
+    /// Basis-point return of `a` minus that of `b` over the last `records`
+    /// oracle records. Positive means `a` outperformed `b`.
+    pub fn relative_performance(env: Env, a: Symbol, b: Symbol, records: u32) -> Option<i128> {
+        let reflector_client = oracle_client(&env);
+
+        let a_history = reflector_client.prices(&ReflectorAsset::Other(a), &records)?;
+        let b_history = reflector_client.prices(&ReflectorAsset::Other(b), &records)?;
+        if a_history.len() < records || b_history.len() < records {
+            return None;
+        }
+
+        window_change_bps(&a_history)?.checked_sub(window_change_bps(&b_history)?)
+    }
+
+    pub fn top_mover(env: Env, symbols: Vec<Symbol>, records: u32) -> Option<(Symbol, i128)> {
+        if symbols.len() > MAX_BATCH_SIZE {
+            panic_with_error!(&env, Error::AssetLimitExceeded);
+        }
+
+        let reflector_client = oracle_client(&env);
+        let mut top: Option<(Symbol, i128)> = None;
+        for symbol in symbols.iter() {
+            let asset = ReflectorAsset::Other(symbol.clone());
+            let Some(change) = reflector_client
+                .prices(&asset, &records)
+                .and_then(|history| window_change_bps(&history))
+            else {
+                continue;
+            };
+
+            let is_larger = match &top {
+                Some((_, top_change)) => change.unsigned_abs() > top_change.unsigned_abs(),
+                None => true,
+            };
+            if is_larger {
+                top = Some((symbol, change));
+            }
+        }
+        top
+    }
+
+    pub fn twap_price(env: Env, symbol: Symbol, records: u32) -> Option<i128> {
+        if records == 0 {
+            return None;
+        }
+
+        let reflector_client = oracle_client(&env);
+        let asset = ReflectorAsset::Other(symbol);
+        let twap = read_twap(&env, &reflector_client, &asset, records)?;
+        to_output_decimals(&env, &reflector_client, twap)
+    }
+}
+
+fn default_max_age(env: &Env) -> u64 {
+    env.storage().instance().get(&DataKey::MaxAge).unwrap()
+}
+
+/// Age limit for a read given the caller's bound and one configured by the
+/// admin: the stricter when both exist, so neither can loosen the other,
+/// and the contract default when there is neither.
+fn resolve_max_age(env: &Env, requested: Option<u64>, configured: Option<u64>) -> u64 {
+    match (requested, configured) {
+        (Some(requested), Some(configured)) => requested.min(configured),
+        (Some(secs), None) | (None, Some(secs)) => secs,
+        (None, None) => default_max_age(env),
+    }
+}
+
+/// Spellings of `input` worth trying against the oracle: the upper-cased
+/// symbol, and its upper-cased prefix when it carries a `_QUOTE` suffix.
+fn symbol_variants(env: &Env, input: &Symbol) -> [Option<Symbol>; 2] {
+    let Ok(raw) = SymbolStr::try_from_val(env, &input.to_symbol_val()) else {
+        return [None, None];
+    };
+    let raw: &[u8] = raw.as_ref();
+
+    let mut upper = [0u8; 32];
+    let upper = &mut upper[..raw.len()];
+    upper.copy_from_slice(raw);
+    upper.make_ascii_uppercase();
+
+    let prefix_len = upper.iter().position(|&byte| byte == b'_').unwrap_or(0);
+    let to_symbol = |bytes: &[u8]| {
+        core::str::from_utf8(bytes)
+            .ok()
+            .map(|text| Symbol::new(env, text))
+    };
+    [
+        to_symbol(upper),
+        match prefix_len {
+            0 => None,
+            len => to_symbol(&upper[..len]),
+        },
+    ]
+}
+
+fn asset_max_age(env: &Env, symbol: &Symbol) -> Option<u64> {
+    env.storage()
+        .persistent()
+        .get(&DataKey::AssetMaxAge(symbol.clone()))
+}
+
+fn ensure_fresh(env: &Env, price_data: &PriceData, max_age_secs: u64) -> Result<(), Error> {
+    // An oracle timestamp ahead of the ledger clock counts as fresh.
+    let age = env
+        .ledger()
+        .timestamp()
+        .saturating_sub(price_data.timestamp);
+    if age > max_age_secs {
+        return Err(Error::InvalidTimestamp);
+    }
+    Ok(())
+}
+
+/// Keeps `price_data` as the fallback `last_good_price` serves for
+/// `symbol`. Only call it with a price that passed `ensure_fresh`. Nothing
+/// is kept while an override is live, since the price may be the override,
+/// and nothing is rewritten unless `price_data` is newer than what is kept.
+fn remember_good_price(env: &Env, symbol: &Symbol, price_data: &PriceData) {
+    if manual_price(env, &ReflectorAsset::Other(symbol.clone())).is_some() {
+        return;
+    }
+    let key = DataKey::LastGood(symbol.clone());
+    let kept: Option<PriceData> = env.storage().persistent().get(&key);
+    if kept.is_some_and(|kept| kept.timestamp >= price_data.timestamp) {
+        return;
+    }
+    env.storage().persistent().set(&key, price_data);
+    env.storage().persistent().extend_ttl(
+        &key,
+        PERSISTENT_LIFETIME_THRESHOLD,
+        PERSISTENT_BUMP_AMOUNT,
+    );
+}
+
+fn last_price_of(env: &Env, asset: &ReflectorAsset) -> Result<i128, Error> {
+    let reflector_client = oracle_client(env);
+    let price_data = current_price(env, &reflector_client, asset);
+
+    price_data
+        .and_then(|data| to_output_decimals(env, &reflector_client, data.price))
+        .ok_or(Error::PriceUnavailable)
+}
+
+/// Moves a price read from `client` into the scale set with
+/// `set_output_decimals`, or the configured oracle's decimals when none is
+/// set. `None` means it does not fit.
+fn to_output_decimals(env: &Env, client: &ReflectorClient, price: i128) -> Option<i128> {
+    let decimals = oracle_decimals(env, client)?;
+    match output_decimals(env)? {
+        output_decimals if output_decimals != decimals => rescale(price, decimals, output_decimals),
+        _ => Some(price),
+    }
+}
+
+fn output_decimals(env: &Env) -> Option<u32> {
+    match env.storage().instance().get(&DataKey::OutputDecimals) {
+        Some(output_decimals) => Some(output_decimals),
+        None => oracle_decimals(env, &oracle_client(env)),
+    }
+}
+
+/// Reads the latest price of `asset` and publishes a `price` event when the
+/// oracle has one, so indexers can see every price the contract consumed.
+/// Cross, TWAP and historical reads publish through the `read_*` helpers
+/// below; the record windows behind the statistics getters are not
+/// published, since they run to hundreds of entries. An oracle that reverts is treated the same as one without a price, and
+/// the oracle's own base asset is always worth exactly one unit. Manual
+/// overrides are not applied here; see `current_price`.
+fn read_price(env: &Env, client: &ReflectorClient, asset: &ReflectorAsset) -> Option<PriceData> {
+    let Some(base) = oracle_base(env, client) else {
+        record_oracle_failure(env, client);
+        return None;
+    };
+    let price_data = if *asset == base {
+        PriceData {
+            price: 10i128.checked_pow(oracle_decimals(env, client)?)?,
+            timestamp: env.ledger().timestamp(),
+        }
+    } else {
+        match client.try_lastprice(asset) {
+            Ok(Ok(Some(price_data))) => {
+                reset_oracle_failures(env, client);
+                price_data
+            }
+            Ok(Ok(None)) => {
+                let counts_missing: bool = env
+                    .storage()
+                    .instance()
+                    .get(&DataKey::BreakerCountsMissing)
+                    .unwrap_or(false);
+                if counts_missing {
+                    record_oracle_failure(env, client);
+                }
+                return None;
+            }
+            _ => {
+                record_oracle_failure(env, client);
+                return None;
+            }
+        }
+    };
+    publish_price(env, asset, &price_data);
+    Some(price_data)
+}
+
+/// Publishes the `price` event for `price_data`, whether it was just read
+/// from the oracle or served from one of the contract's caches.
+fn publish_price(env: &Env, asset: &ReflectorAsset, price_data: &PriceData) {
+    env.events().publish(
+        (Symbol::new(env, "price"), asset.clone()),
+        price_data.clone(),
+    );
+}
+
+/// `x_last_price` of `base` in `quote`, published as a `cross_price` event.
+fn read_cross_price(
+    env: &Env,
+    client: &ReflectorClient,
+    base: &ReflectorAsset,
+    quote: &ReflectorAsset,
+) -> Option<PriceData> {
+    let price_data = client.x_last_price(base, quote)?;
+    env.events().publish(
+        (Symbol::new(env, "cross_price"), base.clone(), quote.clone()),
+        price_data.clone(),
+    );
+    Some(price_data)
+}
+
+/// The record of `asset` at `timestamp`, published as a `price_at` event.
+fn read_price_at(
+    env: &Env,
+    client: &ReflectorClient,
+    asset: &ReflectorAsset,
+    timestamp: u64,
+) -> Option<PriceData> {
+    let price_data = client.price(asset, &timestamp)?;
+    env.events().publish(
+        (Symbol::new(env, "price_at"), asset.clone()),
+        price_data.clone(),
+    );
+    Some(price_data)
+}
+
+/// TWAP of `asset` over `records`, published as a `twap` event carrying
+/// `(records, twap)`.
+fn read_twap(
+    env: &Env,
+    client: &ReflectorClient,
+    asset: &ReflectorAsset,
+    records: u32,
+) -> Option<i128> {
+    let twap = client.twap(asset, &records)?;
+    env.events()
+        .publish((Symbol::new(env, "twap"), asset.clone()), (records, twap));
+    Some(twap)
+}
+
+/// Cross TWAP of `base` in `quote`, published as a `cross_twap` event
+/// carrying `(records, twap)`.
+fn read_cross_twap(
+    env: &Env,
+    client: &ReflectorClient,
+    base: &ReflectorAsset,
+    quote: &ReflectorAsset,
+    records: u32,
+) -> Option<i128> {
+    let twap = client.x_twap(base, quote, &records)?;
+    env.events().publish(
+        (Symbol::new(env, "cross_twap"), base.clone(), quote.clone()),
+        (records, twap),
+    );
+    Some(twap)
+}
+
+/// The price getters answer consumers with: a live `set_manual_price`
+/// override for `asset` if there is one, otherwise `read_price`. Getters
+/// that record, lock or compare oracle prices call `read_price` directly,
+/// so an override never reaches stored state.
+fn current_price(env: &Env, client: &ReflectorClient, asset: &ReflectorAsset) -> Option<PriceData> {
+    manual_override(env, asset).or_else(|| read_price(env, client, asset))
+}
+
+/// `manual_price`, publishing a `manual_price` event when it is served.
+fn manual_override(env: &Env, asset: &ReflectorAsset) -> Option<PriceData> {
+    let price_data = manual_price(env, asset)?;
+    env.events().publish(
+        (Symbol::new(env, "manual_price"), asset.clone()),
+        price_data.clone(),
+    );
+    Some(price_data)
+}
+
+/// The unexpired override for `asset`, stamped with the current ledger time
+/// so freshness checks accept it for as long as it lasts.
+fn manual_price(env: &Env, asset: &ReflectorAsset) -> Option<PriceData> {
+    let ReflectorAsset::Other(symbol) = asset else {
+        return None;
+    };
+    let manual: ManualPrice = env
+        .storage()
+        .persistent()
+        .get(&DataKey::Manual(symbol.clone()))?;
+
+    let now = env.ledger().timestamp();
+    if now > manual.expires_at {
+        return None;
+    }
+    Some(PriceData {
+        price: manual.price,
+        timestamp: now,
+    })
+}
+
+/// Counts a failed read from the configured oracle and trips the pause
+/// switch once `BreakerThreshold` consecutive reads have failed. Reads from
+/// fallback or pool oracles are not counted. Failures recorded by a call
+/// that ends in an error are rolled back with it, which is why keepers
+/// drive the breaker through `heartbeat`.
+fn record_oracle_failure(env: &Env, client: &ReflectorClient) {
+    if client.address != oracle_address(env) {
+        return;
+    }
+    let storage = env.storage().instance();
+    let failures = storage.get(&DataKey::Failures).unwrap_or(0u32) + 1;
+    storage.set(&DataKey::Failures, &failures);
+
+    let threshold: Option<u32> = storage.get(&DataKey::BreakerThreshold);
+    if threshold.is_some_and(|threshold| threshold > 0 && failures >= threshold) {
+        storage.set(&DataKey::Paused, &true);
+        env.events()
+            .publish((Symbol::new(env, "breaker_tripped"),), failures);
+    }
+}
+
+fn reset_oracle_failures(env: &Env, client: &ReflectorClient) {
+    if client.address != oracle_address(env) {
+        return;
+    }
+    let storage = env.storage().instance();
+    if storage.get(&DataKey::Failures).unwrap_or(0u32) != 0 {
+        storage.set(&DataKey::Failures, &0u32);
+    }
+}
+
+/// The XLM price from the configured oracle behind `hello_xlm` and
+/// `self_test`, read once per ledger and held to the XLM max age.
+fn fresh_xlm_price(env: &Env) -> Result<PriceData, Error> {
+    let xlm = Symbol::new(env, "XLM");
+    let max_age_secs = resolve_max_age(env, None, asset_max_age(env, &xlm));
+
+    let price_data = read_price_once_per_ledger(env, &ReflectorAsset::Other(xlm))
+        .ok_or(Error::PriceUnavailable)?;
+    ensure_fresh(env, &price_data, max_age_secs)?;
+    Ok(price_data)
+}
+
+/// Like `read_price`, but reuses the price already read during the current
+/// ledger so repeated calls only reach the oracle once per ledger. Entries
+/// are kept per oracle, so a `set_oracle` takes effect within the ledger.
+fn read_price_once_per_ledger(env: &Env, asset: &ReflectorAsset) -> Option<PriceData> {
+    ensure_not_paused(env);
+    let reflector_client = oracle_client(env);
+    let key = DataKey::LedgerCache(reflector_client.address.clone(), asset.clone());
+    let sequence = env.ledger().sequence();
+
+    let cached: Option<(u32, PriceData)> = env.storage().temporary().get(&key);
+    if let Some((cached_sequence, price_data)) = cached {
+        if cached_sequence == sequence {
+            publish_price(env, asset, &price_data);
+            return Some(price_data);
+        }
+    }
+
+    let price_data = read_price(env, &reflector_client, asset)?;
+    env.storage()
+        .temporary()
+        .set(&key, &(sequence, price_data.clone()));
+    Some(price_data)
+}
+
+/// The decimals of the oracle behind `client`, read once and then served
+/// from instance storage. `None` if the oracle reverts, so a broken feed
+/// reads as missing prices.
+fn oracle_decimals(env: &Env, client: &ReflectorClient) -> Option<u32> {
+    let key = DataKey::Decimals(client.address.clone());
+    if let Some(decimals) = env.storage().instance().get(&key) {
+        return Some(decimals);
+    }
+
+    let Ok(Ok(decimals)) = client.try_decimals() else {
+        return None;
+    };
+    env.storage().instance().set(&key, &decimals);
+    Some(decimals)
+}
+
+/// The base asset of the oracle behind `client`, read once and then served
+/// from instance storage. `None` if the oracle reverts.
+fn oracle_base(env: &Env, client: &ReflectorClient) -> Option<ReflectorAsset> {
+    let key = DataKey::Base(client.address.clone());
+    if let Some(base) = env.storage().instance().get(&key) {
+        return Some(base);
+    }
+
+    let Ok(Ok(base)) = client.try_base() else {
+        return None;
+    };
+    env.storage().instance().set(&key, &base);
+    Some(base)
+}
+
+fn oracle_address(env: &Env) -> Address {
+    env.storage().instance().get(&DataKey::Oracle).unwrap()
+}
+
+fn fallback_oracle_address(env: &Env) -> Option<Address> {
+    env.storage().instance().get(&DataKey::FallbackOracle)
+}
+
+fn oracle_addresses(env: &Env) -> Vec<Address> {
+    ensure_not_paused(env);
+    let mut oracles = Vec::from_array(env, [oracle_address(env)]);
+    let tertiary: Option<Address> = env.storage().instance().get(&DataKey::TertiaryOracle);
+    for extra in [fallback_oracle_address(env), tertiary]
+        .into_iter()
+        .flatten()
+    {
+        oracles.push_back(extra);
+    }
+    oracles
+}
+
+fn aggregation_oracles(env: &Env) -> Vec<Address> {
+    ensure_not_paused(env);
+    env.storage().instance().get(&DataKey::Oracles).unwrap()
+}
+
+/// Cross rate of `base` in `quote` derived from their prices in the oracle
+/// base, in the output decimals.
+fn derive_cross(
+    env: &Env,
+    client: &ReflectorClient,
+    base: &ReflectorAsset,
+    quote: &ReflectorAsset,
+) -> Option<i128> {
+    let base_price = current_price(env, client, base)?.price;
+    let quote_price = current_price(env, client, quote)?.price;
+    let rate = cross_rate(base_price, quote_price, oracle_decimals(env, client)?)?;
+    to_output_decimals(env, client, rate)
+}
+
+/// TWAP of `asset` in the oracle base. The base itself has no history, so
+/// it is priced at exactly one unit, as in `read_price`.
+fn leg_twap(
+    env: &Env,
+    client: &ReflectorClient,
+    asset: &ReflectorAsset,
+    records: u32,
+) -> Option<i128> {
+    if *asset == oracle_base(env, client)? {
+        return 10i128.checked_pow(oracle_decimals(env, client)?);
+    }
+    read_twap(env, client, asset, records)
+}
+
+/// Change in basis points from the oldest to the newest record of an oracle
+/// history, which lists the newest record first.
+fn window_change_bps(history: &Vec<PriceData>) -> Option<i128> {
+    let newest = history.first()?;
+    let oldest = history.last()?;
+    change_bps(oldest.price, newest.price)
+}
+
+fn align_to_resolution(timestamp: u64, resolution: u32) -> u64 {
+    if resolution == 0 {
+        return timestamp;
+    }
+    timestamp - timestamp % resolution as u64
+}
+
+/// Every price read builds its client here, which makes it the place where
+/// the pause switch is enforced.
+fn oracle_client(env: &Env) -> ReflectorClient<'_> {
+    ensure_not_paused(env);
+    ReflectorClient::new(env, &oracle_address(env))
+}
+
+fn ensure_not_paused(env: &Env) {
+    if is_paused(env) {
+        panic_with_error!(env, Error::Paused);
+    }
+}
+
+fn is_paused(env: &Env) -> bool {
+    env.storage()
+        .instance()
+        .get(&DataKey::Paused)
+        .unwrap_or(false)
+}
+
+/// Checks that `oracle` answers the Reflector interface and returns its
+/// version.
+fn probe_oracle(env: &Env, oracle: &Address) -> Result<u32, Error> {
+    match ReflectorClient::new(env, oracle).try_version() {
+        Ok(Ok(version)) => Ok(version),
+        _ => Err(Error::InvalidConfigVersion),
+    }
+}
+
+fn is_allowed(env: &Env, symbol: &Symbol) -> bool {
+    let key = DataKey::Allowed(symbol.clone());
+    if !env.storage().persistent().has(&key) {
+        return false;
+    }
+    env.storage().persistent().extend_ttl(
+        &key,
+        PERSISTENT_LIFETIME_THRESHOLD,
+        PERSISTENT_BUMP_AMOUNT,
+    );
+    true
+}
+
+fn require_admin(env: &Env, admin: &Address) -> Result<(), Error> {
+    admin.require_auth();
+
+    let stored_admin: Address = env.storage().instance().get(&DataKey::Admin).unwrap();
+    if *admin != stored_admin {
+        return Err(Error::Unauthorized);
     }
+    Ok(())
 }
@@ -0,0 +1,481 @@
+use crate::Rounding;
+
+/// Upper bound on Newton iterations in `geometric_mean`.
+const MAX_NEWTON_STEPS: u32 = 32;
+
+/// Most values `geometric_mean` takes, and the limbs their product needs.
+const MAX_FACTORS: usize = 20;
+const WIDE_LIMBS: usize = 2 * MAX_FACTORS;
+
+/// `a * b / denom` truncated towards zero, like `/`. The product is kept in
+/// 256 bits, so this only fails when `denom` is zero or the final result
+/// does not fit in an `i128`.
+pub(crate) fn checked_mul_div(a: i128, b: i128, denom: i128) -> Option<i128> {
+    let (negative, quotient, _, _) = wide_mul_div(a, b, denom)?;
+    to_signed(quotient, negative)
+}
+
+/// `a * b / denom` rounded as requested, with the same overflow guarantees as
+/// `checked_mul_div`. `Trunc` matches `checked_mul_div`; `HalfUp` rounds
+/// ties towards positive infinity.
+pub(crate) fn mul_div_round(a: i128, b: i128, denom: i128, rounding: Rounding) -> Option<i128> {
+    let (negative, quotient, remainder, divisor) = wide_mul_div(a, b, denom)?;
+    if remainder == 0 {
+        return to_signed(quotient, negative);
+    }
+
+    // `quotient` is the magnitude truncated towards zero; decide whether the
+    // rounded magnitude is one above it.
+    let rest = divisor - remainder;
+    let round_away = match rounding {
+        Rounding::Trunc => false,
+        Rounding::Floor => negative,
+        Rounding::Ceil => !negative,
+        Rounding::HalfUp => remainder > rest || (remainder == rest && !negative),
+    };
+    let magnitude = if round_away {
+        quotient.checked_add(1)?
+    } else {
+        quotient
+    };
+    to_signed(magnitude, negative)
+}
+
+/// `base_price / quote_price` in `decimals` fixed point.
+pub(crate) fn cross_rate(base_price: i128, quote_price: i128, decimals: u32) -> Option<i128> {
+    if quote_price == 0 {
+        return None;
+    }
+
+    let scale = 10i128.checked_pow(decimals)?;
+    checked_mul_div(base_price, scale, quote_price)
+}
+
+/// Reciprocal of a `decimals` fixed-point price, kept in the same scale.
+/// Returns `None` for a zero price or a reciprocal that rounds to zero.
+pub(crate) fn invert(price: i128, decimals: u32) -> Option<i128> {
+    if price == 0 {
+        return None;
+    }
+    let scale = 10i128.checked_pow(decimals.checked_mul(2)?)?;
+    match scale / price {
+        0 => None,
+        inverted => Some(inverted),
+    }
+}
+
+/// `value` scaled by `bps` basis points, so `10_000` leaves it unchanged.
+pub(crate) fn apply_bps(value: i128, bps: u32) -> Option<i128> {
+    checked_mul_div(value, bps as i128, 10_000)
+}
+
+/// Signed change from `from` to `to` in basis points of `from`.
+pub(crate) fn change_bps(from: i128, to: i128) -> Option<i128> {
+    if from == 0 {
+        return None;
+    }
+    checked_mul_div(to.checked_sub(from)?, 10_000, from)
+}
+
+/// Absolute distance between `value` and `reference` in basis points of
+/// `reference`. Returns `None` when it cannot be expressed.
+pub(crate) fn deviation_bps(reference: i128, value: i128) -> Option<i128> {
+    if reference == 0 {
+        return None;
+    }
+    let diff = value.checked_sub(reference)?.checked_abs()?;
+    checked_mul_div(diff, 10_000, reference.checked_abs()?)
+}
+
+/// Moves `value` from `from` to `to` decimals, rounding half-up when scaling
+/// down. Returns `None` if the result does not fit in an `i128`.
+pub(crate) fn rescale(value: i128, from: u32, to: u32) -> Option<i128> {
+    if to >= from {
+        let factor = 10i128.checked_pow(to - from)?;
+        return value.checked_mul(factor);
+    }
+
+    let divisor = match 10i128.checked_pow(from - to) {
+        Some(divisor) => divisor,
+        // Anything this far down rounds to zero.
+        None => return Some(0),
+    };
+    let quotient = value.div_euclid(divisor);
+    let remainder = value.rem_euclid(divisor);
+    if remainder >= divisor - remainder {
+        quotient.checked_add(1)
+    } else {
+        Some(quotient)
+    }
+}
+
+/// Geometric mean of strictly positive `values`, rounded down. The product
+/// is kept exactly in a `Wide` and the root found with integer Newton's
+/// method, started from `geometric_mean_bound` so it descends onto the
+/// floor of the root in a handful of steps however spread out the values
+/// are. Returns `None` for empty or non-positive input, or for more than
+/// `MAX_FACTORS` values.
+pub(crate) fn geometric_mean(values: &[i128]) -> Option<i128> {
+    if values.is_empty() || values.len() > MAX_FACTORS {
+        return None;
+    }
+    if values.iter().any(|&value| value <= 0) {
+        return None;
+    }
+    let n = values.len() as u128;
+
+    let mut product = Wide::from_u128(1);
+    for &value in values {
+        product.mul(value as u128)?;
+    }
+
+    let mut estimate = geometric_mean_bound(values);
+    for _ in 0..MAX_NEWTON_STEPS {
+        // `product / estimate^(n - 1)`: repeated floor division is exact.
+        let mut quotient = product;
+        for _ in 1..values.len() {
+            quotient = quotient.div(estimate);
+        }
+        // From above the root the quotient never exceeds the estimate.
+        let quotient = quotient.to_u128()?;
+        if quotient >= estimate {
+            return i128::try_from(estimate).ok();
+        }
+        // `((n - 1) * estimate + quotient) / n`, without the overflow.
+        estimate -= (estimate - quotient).div_ceil(n);
+    }
+    None
+}
+
+/// An upper bound on the geometric mean of positive `values`, within about
+/// 13%. Each `log2` is read off the bit length and the next 16 bits, using
+/// `log2(1 + f) >= f` with its slack added back, and the mean is raised back
+/// with `2^f <= 1 + f`. Capped at the largest value, which bounds it too.
+fn geometric_mean_bound(values: &[i128]) -> u128 {
+    const FRACTION_BITS: u32 = 16;
+    const ONE: u128 = 1 << FRACTION_BITS;
+    // `max(log2(1 + f) - f)` is 0.0861, plus a unit for truncating `f`.
+    const LOG_SLACK: u128 = 5_645;
+
+    let mut log_sum = 0u128;
+    let mut max = 0u128;
+    for &value in values {
+        let value = value as u128;
+        max = max.max(value);
+        let shift = value.leading_zeros();
+        let fraction = ((value << shift) >> (127 - FRACTION_BITS)) - ONE;
+        log_sum += (127 - shift) as u128 * ONE + fraction + LOG_SLACK;
+    }
+
+    let mean = log_sum.div_ceil(values.len() as u128);
+    let exponent = (mean >> FRACTION_BITS) as u32;
+    let mantissa = ONE + (mean & (ONE - 1));
+    let bound = if exponent >= FRACTION_BITS {
+        mantissa << (exponent - FRACTION_BITS)
+    } else {
+        (mantissa << exponent).div_ceil(ONE)
+    };
+    bound.min(max)
+}
+
+/// Unsigned integer wide enough for the product of `MAX_FACTORS` positive
+/// `i128` values, as little-endian 64-bit limbs.
+#[derive(Clone, Copy)]
+struct Wide {
+    limbs: [u64; WIDE_LIMBS],
+    len: usize,
+}
+
+impl Wide {
+    fn from_u128(value: u128) -> Self {
+        let mut wide = Wide {
+            limbs: [0; WIDE_LIMBS],
+            len: 2,
+        };
+        wide.limbs[0] = value as u64;
+        wide.limbs[1] = (value >> 64) as u64;
+        wide.trim();
+        wide
+    }
+
+    /// Multiplies in place, or returns `None` if the result could outgrow
+    /// the limbs.
+    fn mul(&mut self, factor: u128) -> Option<()> {
+        if self.len + 2 > WIDE_LIMBS {
+            return None;
+        }
+
+        let mut product = [0u64; WIDE_LIMBS];
+        for (offset, part) in [factor as u64, (factor >> 64) as u64]
+            .into_iter()
+            .enumerate()
+        {
+            let mut carry = 0u128;
+            for i in 0..self.len {
+                let sum =
+                    self.limbs[i] as u128 * part as u128 + product[i + offset] as u128 + carry;
+                product[i + offset] = sum as u64;
+                carry = sum >> 64;
+            }
+            product[self.len + offset] = carry as u64;
+        }
+        self.limbs = product;
+        self.len += 2;
+        self.trim();
+        Some(())
+    }
+
+    /// Floor division by a non-zero `divisor`.
+    fn div(&self, divisor: u128) -> Wide {
+        let mut quotient = Wide {
+            limbs: [0; WIDE_LIMBS],
+            len: self.len,
+        };
+        let mut remainder = 0u128;
+        for i in (0..self.len).rev() {
+            let limb = self.limbs[i];
+            if divisor >> 64 == 0 {
+                // The remainder is below the divisor, so it fits in 64 bits.
+                let dividend = (remainder << 64) | limb as u128;
+                quotient.limbs[i] = (dividend / divisor) as u64;
+                remainder = dividend % divisor;
+                continue;
+            }
+            for bit in (0..64).rev() {
+                // As in `div_wide`, `carry` holds the 129th bit.
+                let carry = remainder >> 127;
+                remainder = (remainder << 1) | ((limb >> bit) & 1) as u128;
+                quotient.limbs[i] <<= 1;
+                if carry == 1 || remainder >= divisor {
+                    remainder = remainder.wrapping_sub(divisor);
+                    quotient.limbs[i] |= 1;
+                }
+            }
+        }
+        quotient.trim();
+        quotient
+    }
+
+    fn to_u128(self) -> Option<u128> {
+        if self.len > 2 {
+            return None;
+        }
+        Some(self.limbs[0] as u128 | (self.limbs[1] as u128) << 64)
+    }
+
+    fn trim(&mut self) {
+        while self.len > 0 && self.limbs[self.len - 1] == 0 {
+            self.len -= 1;
+        }
+    }
+}
+
+/// Splits `a * b / denom` into its sign, the truncated magnitude of the
+/// quotient, the remainder and the magnitude of the divisor.
+fn wide_mul_div(a: i128, b: i128, denom: i128) -> Option<(bool, u128, u128, u128)> {
+    if denom == 0 {
+        return None;
+    }
+    let negative = (a < 0) ^ (b < 0) ^ (denom < 0);
+    let divisor = denom.unsigned_abs();
+
+    let (high, low) = mul_wide(a.unsigned_abs(), b.unsigned_abs());
+    let (quotient, remainder) = div_wide(high, low, divisor)?;
+    Some((negative, quotient, remainder, divisor))
+}
+
+/// Full 256-bit product of `a` and `b` as `(high, low)` halves.
+fn mul_wide(a: u128, b: u128) -> (u128, u128) {
+    const MASK: u128 = u64::MAX as u128;
+    let (a_low, a_high) = (a & MASK, a >> 64);
+    let (b_low, b_high) = (b & MASK, b >> 64);
+
+    let low_low = a_low * b_low;
+    let low_high = a_low * b_high;
+    let high_low = a_high * b_low;
+    let high_high = a_high * b_high;
+
+    let middle = (low_low >> 64) + (low_high & MASK) + (high_low & MASK);
+    let low = (low_low & MASK) | (middle << 64);
+    let high = high_high + (low_high >> 64) + (high_low >> 64) + (middle >> 64);
+    (high, low)
+}
+
+/// Divides the 256-bit value `(high, low)` by `divisor` with schoolbook long
+/// division. Returns `None` when the quotient does not fit in 128 bits.
+fn div_wide(high: u128, low: u128, divisor: u128) -> Option<(u128, u128)> {
+    if high >= divisor {
+        return None;
+    }
+
+    let mut remainder = high;
+    let mut quotient = 0u128;
+    for bit in (0..128).rev() {
+        // The shifted remainder can need 129 bits; `carry` holds the top one.
+        let carry = remainder >> 127;
+        remainder = (remainder << 1) | ((low >> bit) & 1);
+        quotient <<= 1;
+        if carry == 1 || remainder >= divisor {
+            remainder = remainder.wrapping_sub(divisor);
+            quotient |= 1;
+        }
+    }
+    Some((quotient, remainder))
+}
+
+fn to_signed(magnitude: u128, negative: bool) -> Option<i128> {
+    if negative {
+        0i128.checked_sub_unsigned(magnitude)
+    } else {
+        i128::try_from(magnitude).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mul_div_keeps_wide_products() {
+        assert_eq!(
+            checked_mul_div(i128::MAX, i128::MAX, i128::MAX),
+            Some(i128::MAX)
+        );
+        assert_eq!(
+            checked_mul_div(i128::MIN, i128::MAX, i128::MAX),
+            Some(i128::MIN)
+        );
+        assert_eq!(checked_mul_div(i128::MIN, 1, 1), Some(i128::MIN));
+        assert_eq!(checked_mul_div(-7, 1, 2), Some(-3));
+    }
+
+    #[test]
+    fn mul_div_rejects_overflow_and_zero_denominator() {
+        assert_eq!(checked_mul_div(i128::MIN, -1, 1), None);
+        assert_eq!(checked_mul_div(i128::MAX, 2, 1), None);
+        assert_eq!(checked_mul_div(1, 1, 0), None);
+        assert_eq!(mul_div_round(1, 1, 0, Rounding::HalfUp), None);
+    }
+
+    #[test]
+    fn mul_div_round_follows_sign() {
+        assert_eq!(mul_div_round(7, 1, 2, Rounding::Floor), Some(3));
+        assert_eq!(mul_div_round(7, 1, 2, Rounding::Ceil), Some(4));
+        assert_eq!(mul_div_round(-7, 1, 2, Rounding::Floor), Some(-4));
+        assert_eq!(mul_div_round(-7, 1, 2, Rounding::Ceil), Some(-3));
+        assert_eq!(mul_div_round(7, -1, -2, Rounding::Floor), Some(3));
+        assert_eq!(mul_div_round(7, 1, 2, Rounding::Trunc), Some(3));
+        assert_eq!(mul_div_round(-7, 1, 2, Rounding::Trunc), Some(-3));
+    }
+
+    #[test]
+    fn mul_div_round_breaks_ties_upwards() {
+        assert_eq!(mul_div_round(7, 1, 2, Rounding::HalfUp), Some(4));
+        assert_eq!(mul_div_round(-7, 1, 2, Rounding::HalfUp), Some(-3));
+        assert_eq!(mul_div_round(5, 1, 3, Rounding::HalfUp), Some(2));
+        assert_eq!(mul_div_round(-5, 1, 3, Rounding::HalfUp), Some(-2));
+        assert_eq!(mul_div_round(4, 1, 3, Rounding::HalfUp), Some(1));
+    }
+
+    #[test]
+    fn mul_div_round_at_the_i128_edges() {
+        // (2^64 + 1) * (2^64 - 1) = 2 * i128::MAX + 1.
+        let a = (1i128 << 64) + 1;
+        let b = (1i128 << 64) - 1;
+        assert_eq!(mul_div_round(a, b, 2, Rounding::Floor), Some(i128::MAX));
+        assert_eq!(mul_div_round(a, b, 2, Rounding::Ceil), None);
+        assert_eq!(mul_div_round(-a, b, 2, Rounding::Ceil), Some(-i128::MAX));
+        assert_eq!(mul_div_round(-a, b, 2, Rounding::Floor), Some(i128::MIN));
+        assert_eq!(
+            mul_div_round(i128::MAX, i128::MAX, i128::MAX, Rounding::Ceil),
+            Some(i128::MAX)
+        );
+    }
+
+    #[test]
+    fn rescale_at_the_edges() {
+        assert_eq!(rescale(15, 1, 0), Some(2));
+        assert_eq!(rescale(-15, 1, 0), Some(-1));
+        assert_eq!(rescale(14, 1, 0), Some(1));
+        assert_eq!(rescale(1, 0, 38), Some(10i128.pow(38)));
+        assert_eq!(rescale(2, 0, 38), None);
+        assert_eq!(rescale(i128::MAX, 0, 1), None);
+        assert_eq!(rescale(i128::MAX, 38, 0), Some(2));
+        assert_eq!(rescale(i128::MAX, 39, 0), Some(0));
+        assert_eq!(rescale(i128::MIN, 38, 0), Some(-2));
+    }
+
+    #[test]
+    fn invert_at_the_edges() {
+        assert_eq!(invert(0, 7), None);
+        assert_eq!(invert(10i128.pow(7), 7), Some(10i128.pow(7)));
+        assert_eq!(invert(2 * 10i128.pow(7), 7), Some(5_000_000));
+        assert_eq!(invert(1, 19), Some(10i128.pow(38)));
+        assert_eq!(invert(1, 20), None);
+        assert_eq!(invert(i128::MAX, 7), None);
+        assert_eq!(invert(-1, 0), Some(-1));
+    }
+
+    #[test]
+    fn bps_helpers_reject_zero_references() {
+        assert_eq!(apply_bps(i128::MAX, 10_000), Some(i128::MAX));
+        assert_eq!(change_bps(0, 5), None);
+        assert_eq!(change_bps(100, 90), Some(-1_000));
+        assert_eq!(deviation_bps(0, 5), None);
+        assert_eq!(deviation_bps(-100, -90), Some(1_000));
+    }
+
+    #[test]
+    fn geometric_mean_of_two_assets() {
+        let scale = 10i128.pow(14);
+        assert_eq!(geometric_mean(&[4, 9]), Some(6));
+        assert_eq!(geometric_mean(&[4 * scale, 9 * scale]), Some(6 * scale));
+        assert_eq!(geometric_mean(&[2, 3]), Some(2));
+        assert_eq!(
+            geometric_mean(&[scale, 2 * scale]),
+            Some(141_421_356_237_309)
+        );
+        assert_eq!(
+            geometric_mean(&[1, i128::MAX]),
+            Some(13_043_817_825_332_782_212)
+        );
+        assert_eq!(geometric_mean(&[i128::MAX, i128::MAX]), Some(i128::MAX));
+    }
+
+    #[test]
+    fn geometric_mean_of_three_assets() {
+        let scale = 10i128.pow(14);
+        assert_eq!(geometric_mean(&[2, 4, 8]), Some(4));
+        assert_eq!(
+            geometric_mean(&[2 * scale, 4 * scale, 8 * scale]),
+            Some(4 * scale)
+        );
+        assert_eq!(
+            geometric_mean(&[3 * scale / 10, 60_000 * scale, scale]),
+            Some(2_620_741_394_208_896)
+        );
+    }
+
+    #[test]
+    fn geometric_mean_of_spread_out_baskets() {
+        let mut values = [1i128; 20];
+        values[0] = 1 << 60;
+        assert_eq!(geometric_mean(&values), Some(8));
+
+        let mut values = [1_000_000_000i128; 20];
+        values[0] = 6 * 10i128.pow(18);
+        assert_eq!(geometric_mean(&values), Some(3_082_531_778));
+
+        assert_eq!(geometric_mean(&[i128::MAX; 20]), Some(i128::MAX));
+        assert_eq!(geometric_mean(&[7; 20]), Some(7));
+    }
+
+    #[test]
+    fn geometric_mean_rejects_bad_input() {
+        assert_eq!(geometric_mean(&[]), None);
+        assert_eq!(geometric_mean(&[0, 3]), None);
+        assert_eq!(geometric_mean(&[-1, 4]), None);
+        assert_eq!(geometric_mean(&[1; 21]), None);
+        assert_eq!(geometric_mean(&[5]), Some(5));
+    }
+}
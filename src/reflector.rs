@@ -41,21 +41,21 @@ pub trait Contract {
     fn admin(e: Env) -> Option<Address>;
 }
 
-#[contracttype(export = false)]
+#[contracttype]
 #[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd)]
 pub enum Asset {
     Stellar(Address),
     Other(Symbol),
 }
 
-#[contracttype(export = false)]
+#[contracttype]
 #[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd)]
 pub struct PriceData {
     pub price: i128,
     pub timestamp: u64,
 }
 
-#[soroban_sdk::contracterror(export = false)]
+#[soroban_sdk::contracterror]
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
 pub enum Error {
     AlreadyInitialized = 0,
@@ -66,4 +66,8 @@ pub enum Error {
     InvalidTimestamp = 5,
     InvalidUpdateLength = 6,
     AssetLimitExceeded = 7,
+    PriceUnavailable = 8,
+    PriceDeviation = 9,
+    Paused = 10,
+    OutOfBounds = 11,
 }
@@ -0,0 +1,552 @@
+extern crate std;
+
+use crate::reflector::{Asset, Error, PriceData};
+use crate::{AggStrategy, Contract, ContractClient, PriceSource, Rounding};
+use soroban_sdk::testutils::{Address as _, Events, Ledger};
+use soroban_sdk::{contract, contractimpl, contracttype, vec, Address, Env, IntoVal, Symbol, Vec};
+
+#[contracttype]
+enum MockKey {
+    Base,
+    Decimals,
+    Broken,
+    Lag,
+    Price(Asset),
+}
+
+/// A Reflector stand-in whose base, decimals and prices are set by the test.
+/// Every price it returns is stamped with the current ledger time, less the
+/// lag set with `set_lag`.
+#[contract]
+struct MockOracle;
+
+#[contractimpl]
+impl MockOracle {
+    pub fn __constructor(env: Env, base: Asset, decimals: u32) {
+        env.storage().instance().set(&MockKey::Base, &base);
+        env.storage().instance().set(&MockKey::Decimals, &decimals);
+    }
+
+    pub fn set_price(env: Env, asset: Asset, price: i128) {
+        env.storage().instance().set(&MockKey::Price(asset), &price);
+    }
+
+    /// Makes every price read revert.
+    pub fn set_broken(env: Env, broken: bool) {
+        env.storage().instance().set(&MockKey::Broken, &broken);
+    }
+
+    /// Makes every price read look `lag` seconds old.
+    pub fn set_lag(env: Env, lag: u64) {
+        env.storage().instance().set(&MockKey::Lag, &lag);
+    }
+
+    pub fn base(env: Env) -> Asset {
+        env.storage().instance().get(&MockKey::Base).unwrap()
+    }
+
+    pub fn assets(env: Env) -> Vec<Asset> {
+        Vec::new(&env)
+    }
+
+    pub fn decimals(env: Env) -> u32 {
+        env.storage().instance().get(&MockKey::Decimals).unwrap()
+    }
+
+    pub fn version(_env: Env) -> u32 {
+        3
+    }
+
+    pub fn lastprice(env: Env, asset: Asset) -> Option<PriceData> {
+        let broken: bool = env
+            .storage()
+            .instance()
+            .get(&MockKey::Broken)
+            .unwrap_or(false);
+        if broken {
+            panic!("oracle is down");
+        }
+        let price = env.storage().instance().get(&MockKey::Price(asset))?;
+        let lag: u64 = env.storage().instance().get(&MockKey::Lag).unwrap_or(0);
+        Some(PriceData {
+            price,
+            timestamp: env.ledger().timestamp() - lag,
+        })
+    }
+}
+
+fn other(env: &Env, symbol: &str) -> Asset {
+    Asset::Other(Symbol::new(env, symbol))
+}
+
+fn register_oracle(env: &Env, base: &str, decimals: u32) -> MockOracleClient<'static> {
+    let address = env.register(MockOracle, (other(env, base), decimals));
+    MockOracleClient::new(env, &address)
+}
+
+struct Setup {
+    env: Env,
+    admin: Address,
+    oracle: MockOracleClient<'static>,
+    contract: ContractClient<'static>,
+}
+
+/// Deploys the contract over two mock oracles quoting in USD with 14
+/// decimals, with XLM at 0.12 on both.
+fn setup() -> Setup {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.ledger().set_timestamp(1_000_000);
+
+    let oracle = register_oracle(&env, "USD", 14);
+    let fallback = register_oracle(&env, "USD", 14);
+    for feed in [&oracle, &fallback] {
+        feed.set_price(&other(&env, "XLM"), &12_000_000_000_000);
+    }
+
+    let admin = Address::generate(&env);
+    let address = env.register(
+        Contract,
+        (
+            admin.clone(),
+            oracle.address.clone(),
+            Some(fallback.address.clone()),
+            Option::<Address>::None,
+            900u64,
+            Symbol::new(&env, "XLM"),
+            AggStrategy::Median,
+        ),
+    );
+    let contract = ContractClient::new(&env, &address);
+    Setup {
+        env,
+        admin,
+        oracle,
+        contract,
+    }
+}
+
+#[test]
+fn migrating_oracles_keeps_each_oracles_base_and_decimals() {
+    let Setup {
+        env,
+        admin,
+        contract,
+        ..
+    } = setup();
+    let xlm = Symbol::new(&env, "XLM");
+    let usdc = Symbol::new(&env, "USDC");
+    let old_oracle = register_oracle(&env, "USDC", 14);
+    old_oracle.set_price(&other(&env, "XLM"), &12_000_000_000_000);
+    contract.set_oracle(&admin, &old_oracle.address);
+    contract.add_oracle(&admin, &old_oracle.address);
+    assert_eq!(contract.require_price(&usdc), 100_000_000_000_000);
+
+    let new_oracle = register_oracle(&env, "USD", 7);
+    new_oracle.set_price(&other(&env, "XLM"), &1_200_000);
+    new_oracle.set_price(&other(&env, "USDC"), &9_990_000);
+    contract.set_oracle(&admin, &new_oracle.address);
+
+    // The pool still holds the old oracle.
+    assert_eq!(contract.agg_price(&xlm), Some(1_200_000));
+    assert_eq!(contract.last_price_scaled(&xlm), Some((1_200_000, 7)));
+    assert_eq!(contract.require_price(&usdc), 9_990_000);
+}
+
+#[test]
+fn breaker_trips_after_threshold_and_reset_lifts_it() {
+    let Setup {
+        admin,
+        oracle,
+        contract,
+        ..
+    } = setup();
+    contract.set_breaker_threshold(&admin, &2, &false);
+    oracle.set_broken(&true);
+
+    assert!(!contract.heartbeat());
+    assert_eq!(contract.try_hello_xlm(), Err(Ok(Error::PriceUnavailable)));
+    assert!(!contract.heartbeat());
+    assert_eq!(contract.try_hello_xlm(), Err(Ok(Error::Paused)));
+
+    oracle.set_broken(&false);
+    assert!(!contract.heartbeat());
+    contract.reset_breaker(&admin);
+    assert!(contract.heartbeat());
+    assert_eq!(contract.hello_xlm(), 12_000_000_000_000);
+}
+
+#[test]
+fn unpause_clears_the_failure_count() {
+    let Setup {
+        admin,
+        oracle,
+        contract,
+        ..
+    } = setup();
+    contract.set_breaker_threshold(&admin, &3, &false);
+    oracle.set_broken(&true);
+    for _ in 0..3 {
+        contract.heartbeat();
+    }
+    contract.unpause(&admin);
+
+    // One more failure is not enough to trip again.
+    assert!(!contract.heartbeat());
+    oracle.set_broken(&false);
+    assert!(contract.heartbeat());
+}
+
+#[test]
+fn set_oracle_clears_the_failure_count() {
+    let Setup {
+        env,
+        admin,
+        oracle,
+        contract,
+    } = setup();
+    contract.set_breaker_threshold(&admin, &2, &false);
+    oracle.set_broken(&true);
+    contract.heartbeat();
+
+    let new_oracle = register_oracle(&env, "USD", 14);
+    new_oracle.set_broken(&true);
+    contract.set_oracle(&admin, &new_oracle.address);
+    assert!(!contract.heartbeat());
+    new_oracle.set_broken(&false);
+    new_oracle.set_price(&other(&env, "XLM"), &12_000_000_000_000);
+    assert!(contract.heartbeat());
+}
+
+#[test]
+fn zero_threshold_disarms_the_breaker() {
+    let Setup {
+        admin,
+        oracle,
+        contract,
+        ..
+    } = setup();
+    contract.set_breaker_threshold(&admin, &0, &false);
+    oracle.set_broken(&true);
+    for _ in 0..3 {
+        assert!(!contract.heartbeat());
+    }
+    oracle.set_broken(&false);
+    assert!(contract.heartbeat());
+}
+
+#[test]
+fn cross_and_candidate_prices_answer_in_output_decimals() {
+    let Setup {
+        env,
+        admin,
+        oracle,
+        contract,
+    } = setup();
+    let xlm = Symbol::new(&env, "XLM");
+    let btc = Symbol::new(&env, "BTC");
+    oracle.set_price(&other(&env, "BTC"), &6_000_000_000_000_000_000);
+    contract.set_output_decimals(&admin, &7);
+
+    assert_eq!(
+        contract.cross_breakdown(&btc, &xlm),
+        Some((600_000_000_000, 1_200_000, 5_000_000_000_000))
+    );
+    assert_eq!(contract.derived_cross(&btc, &xlm), Some(5_000_000_000_000));
+
+    let candidate = register_oracle(&env, "USD", 10);
+    candidate.set_price(&other(&env, "XLM"), &1_200_000_000);
+    assert_eq!(
+        contract.last_price_from(&admin, &candidate.address, &xlm),
+        1_200_000
+    );
+}
+
+#[test]
+fn manual_override_is_served_until_it_expires() {
+    let Setup {
+        env,
+        admin,
+        contract,
+        ..
+    } = setup();
+    let xlm = Symbol::new(&env, "XLM");
+    let now = env.ledger().timestamp();
+    assert_eq!(
+        contract.try_set_manual_price(&admin, &xlm, &0, &(now + 60)),
+        Err(Ok(Error::OutOfBounds))
+    );
+    contract.set_manual_price(&admin, &xlm, &11_000_000_000_000, &(now + 60));
+
+    assert_eq!(contract.require_price(&xlm), 11_000_000_000_000);
+    assert_eq!(
+        contract.last_good_price(&xlm, &None),
+        Some((11_000_000_000_000, PriceSource::Manual))
+    );
+    contract.record_price(&xlm);
+    assert_eq!(
+        contract.history(&xlm).get(0).unwrap().price,
+        12_000_000_000_000
+    );
+
+    env.ledger().set_timestamp(now + 61);
+    assert_eq!(contract.require_price(&xlm), 12_000_000_000_000);
+    assert_eq!(
+        contract.last_good_price(&xlm, &None),
+        Some((12_000_000_000_000, PriceSource::Live))
+    );
+}
+
+#[test]
+fn ledger_cache_hits_still_publish_the_price() {
+    let Setup { env, contract, .. } = setup();
+    let xlm_asset = other(&env, "XLM");
+    let data = PriceData {
+        price: 12_000_000_000_000,
+        timestamp: env.ledger().timestamp(),
+    };
+
+    contract.hello_xlm();
+    contract.hello_xlm();
+    assert_eq!(
+        env.events().all(),
+        vec![
+            &env,
+            (
+                contract.address.clone(),
+                (Symbol::new(&env, "price"), xlm_asset).into_val(&env),
+                data.into_val(&env),
+            ),
+        ]
+    );
+}
+
+#[test]
+fn last_good_price_falls_back_to_the_newest_fresh_read() {
+    let Setup {
+        env,
+        oracle,
+        contract,
+        ..
+    } = setup();
+    let xlm = Symbol::new(&env, "XLM");
+    let now = env.ledger().timestamp();
+    assert_eq!(
+        contract.price_with_freshness(&xlm, &None),
+        Some((12_000_000_000_000, true))
+    );
+
+    env.ledger().set_timestamp(now + 300);
+    oracle.set_price(&other(&env, "XLM"), &13_000_000_000_000);
+    assert_eq!(contract.fresh_price(&xlm, &None), 13_000_000_000_000);
+
+    oracle.set_broken(&true);
+    env.ledger().set_timestamp(now + 10_000);
+    assert_eq!(
+        contract.last_good_price(&xlm, &None),
+        Some((13_000_000_000_000, PriceSource::LastGood))
+    );
+}
+
+#[test]
+fn cached_price_respects_the_default_ttl_and_the_max_age() {
+    let Setup {
+        env,
+        admin,
+        oracle,
+        contract,
+    } = setup();
+    let xlm = Symbol::new(&env, "XLM");
+    let now = env.ledger().timestamp();
+    contract.set_default_cache_ttl(&admin, &60);
+    assert_eq!(contract.cached_price(&xlm, &None), 12_000_000_000_000);
+
+    oracle.set_price(&other(&env, "XLM"), &13_000_000_000_000);
+    env.ledger().set_timestamp(now + 60);
+    assert_eq!(contract.cached_price(&xlm, &None), 12_000_000_000_000);
+    env.ledger().set_timestamp(now + 61);
+    assert_eq!(contract.cached_price(&xlm, &None), 13_000_000_000_000);
+
+    // A long TTL does not stretch the 900 second max age.
+    contract.set_cache_ttl(&admin, &xlm, &10_000);
+    oracle.set_price(&other(&env, "XLM"), &14_000_000_000_000);
+    env.ledger().set_timestamp(now + 961);
+    assert_eq!(contract.cached_price(&xlm, &None), 13_000_000_000_000);
+    env.ledger().set_timestamp(now + 962);
+    assert_eq!(contract.cached_price(&xlm, &None), 14_000_000_000_000);
+}
+
+#[test]
+fn set_oracle_takes_effect_within_the_ledger() {
+    let Setup {
+        env,
+        admin,
+        contract,
+        ..
+    } = setup();
+    assert_eq!(contract.hello_xlm(), 12_000_000_000_000);
+
+    let new_oracle = register_oracle(&env, "USD", 14);
+    new_oracle.set_price(&other(&env, "XLM"), &13_000_000_000_000);
+    contract.set_oracle(&admin, &new_oracle.address);
+    assert_eq!(contract.hello_xlm(), 13_000_000_000_000);
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #4)")]
+fn constructor_probes_the_fallback_oracle() {
+    let env = Env::default();
+    let oracle = register_oracle(&env, "USD", 14);
+    env.register(
+        Contract,
+        (
+            Address::generate(&env),
+            oracle.address.clone(),
+            Some(Address::generate(&env)),
+            Option::<Address>::None,
+            900u64,
+            Symbol::new(&env, "XLM"),
+            AggStrategy::Median,
+        ),
+    );
+}
+
+#[test]
+fn pause_blocks_stored_and_live_prices() {
+    let Setup {
+        env,
+        admin,
+        contract,
+        ..
+    } = setup();
+    let xlm = Symbol::new(&env, "XLM");
+    contract.observe(&xlm);
+    contract.pause(&admin);
+
+    assert_eq!(contract.try_last_price(&xlm), Err(Ok(Error::Paused)));
+    // Getters without an error type surface the contract error raw.
+    let paused = Err(Ok(soroban_sdk::Error::from_contract_error(
+        Error::Paused as u32,
+    )));
+    assert_eq!(contract.try_history(&xlm).map(|_| ()), paused);
+    assert_eq!(contract.try_locked_price(&xlm, &1).map(|_| ()), paused);
+    assert_eq!(contract.try_high_watermark(&xlm).map(|_| ()), paused);
+    assert_eq!(contract.try_low_watermark(&xlm).map(|_| ()), paused);
+
+    contract.unpause(&admin);
+    assert_eq!(contract.high_watermark(&xlm), Some(12_000_000_000_000));
+}
+
+#[test]
+fn value_of_truncates_negative_amounts_by_default() {
+    let Setup { env, contract, .. } = setup();
+    let xlm = Symbol::new(&env, "XLM");
+    assert_eq!(contract.value_of(&xlm, &10, &None), Some(1));
+    assert_eq!(contract.value_of(&xlm, &-10, &None), Some(-1));
+    assert_eq!(
+        contract.value_of(&xlm, &-10, &Some(Rounding::Floor)),
+        Some(-2)
+    );
+}
+
+#[test]
+fn per_asset_max_age_caps_every_freshness_check() {
+    let Setup {
+        env,
+        admin,
+        oracle,
+        contract,
+    } = setup();
+    let xlm = Symbol::new(&env, "XLM");
+    oracle.set_lag(&61);
+    assert_eq!(
+        contract.price_with_freshness(&xlm, &Some(900)),
+        Some((12_000_000_000_000, true))
+    );
+
+    contract.set_asset_max_age(&admin, &xlm, &60);
+    assert_eq!(
+        contract.price_with_freshness(&xlm, &Some(900)),
+        Some((12_000_000_000_000, false))
+    );
+    assert_eq!(
+        contract.try_fresh_price(&xlm, &Some(900)),
+        Err(Ok(Error::InvalidTimestamp))
+    );
+}
+
+#[test]
+fn admin_transfer_needs_the_pending_admin_to_accept() {
+    let Setup {
+        env,
+        admin,
+        contract,
+        ..
+    } = setup();
+    let new_admin = Address::generate(&env);
+    let stranger = Address::generate(&env);
+
+    assert_eq!(
+        contract.try_propose_admin(&stranger, &stranger),
+        Err(Ok(Error::Unauthorized))
+    );
+    contract.propose_admin(&admin, &new_admin);
+    assert_eq!(env.auths()[0].0, admin);
+    assert_eq!(
+        contract.try_accept_admin(&stranger),
+        Err(Ok(Error::Unauthorized))
+    );
+    assert_eq!(contract.get_admin(), admin);
+
+    contract.accept_admin(&new_admin);
+    assert_eq!(contract.get_admin(), new_admin);
+    assert_eq!(
+        contract.try_accept_admin(&new_admin),
+        Err(Ok(Error::Unauthorized))
+    );
+    assert_eq!(contract.try_pause(&admin), Err(Ok(Error::Unauthorized)));
+    contract.pause(&new_admin);
+}
+
+#[test]
+fn history_keeps_the_newest_records_oldest_first() {
+    let Setup {
+        env,
+        oracle,
+        contract,
+        ..
+    } = setup();
+    let xlm = Symbol::new(&env, "XLM");
+    let recorded = crate::HISTORY_SIZE as i128 + 5;
+    for price in 1..=recorded {
+        oracle.set_price(&other(&env, "XLM"), &price);
+        contract.record_price(&xlm);
+    }
+
+    let history = contract.history(&xlm);
+    assert_eq!(history.len(), crate::HISTORY_SIZE);
+    let prices: std::vec::Vec<i128> = history.iter().map(|data| data.price).collect();
+    let expected: std::vec::Vec<i128> = (6..=recorded).collect();
+    assert_eq!(prices, expected);
+}
+
+#[test]
+fn a_round_can_only_be_locked_once() {
+    let Setup {
+        env,
+        admin,
+        oracle,
+        contract,
+    } = setup();
+    let xlm = Symbol::new(&env, "XLM");
+    assert_eq!(contract.lock_price(&admin, &xlm, &7), 12_000_000_000_000);
+
+    oracle.set_price(&other(&env, "XLM"), &13_000_000_000_000);
+    assert_eq!(
+        contract.try_lock_price(&admin, &xlm, &7),
+        Err(Ok(Error::AssetAlreadyExists))
+    );
+    assert_eq!(contract.locked_price(&xlm, &7), Some(12_000_000_000_000));
+    assert_eq!(contract.lock_price(&admin, &xlm, &8), 13_000_000_000_000);
+    assert_eq!(contract.locked_price(&xlm, &9), None);
+}